@@ -1,45 +1,106 @@
 #![allow(unused)]
 
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-use crate::util::TrimTrailingZerosToString;
+use crate::util::NumberFormat;
 
-#[repr(u64)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ByteFormat {
-  Bytes = 0,
-  KB = 1000,
-  KiB = 1024,
-  MB = 1000u64.pow(2),
-  MiB = 1024u64.pow(2),
-  GB = 1000u64.pow(3),
-  GiB = 1024u64.pow(3),
-  TB = 1000u64.pow(4),
-  TiB = 1024u64.pow(4),
+  Bytes,
+  KB,
+  KiB,
+  MB,
+  MiB,
+  GB,
+  GiB,
+  TB,
+  TiB,
+  PB,
+  PiB,
+  EB,
+  EiB,
+  /// 1000 bits
+  Kbit,
+  Mbit,
+  Gbit,
+  /// Largest binary unit that keeps the value at least `1`
+  Auto,
+  /// Largest decimal unit that keeps the value at least `1`
+  AutoSi,
 }
 
+const IEC_UNITS: &[ByteFormat] = &[
+  ByteFormat::Bytes, ByteFormat::KiB, ByteFormat::MiB, ByteFormat::GiB, ByteFormat::TiB, ByteFormat::PiB, ByteFormat::EiB,
+];
+const SI_UNITS: &[ByteFormat] = &[
+  ByteFormat::Bytes, ByteFormat::KB, ByteFormat::MB, ByteFormat::GB, ByteFormat::TB, ByteFormat::PB, ByteFormat::EB,
+];
+
 impl ByteFormat {
+  /// Bytes per unit, auto formats count as bytes until they are resolved
   fn as_u64(&self) -> u64 {
-    unsafe {
-      std::mem::transmute(*self)
+    match self {
+      ByteFormat::Bytes | ByteFormat::Auto | ByteFormat::AutoSi => 1,
+      ByteFormat::KB => 1000,
+      ByteFormat::KiB => 1024,
+      ByteFormat::MB => 1000u64.pow(2),
+      ByteFormat::MiB => 1024u64.pow(2),
+      ByteFormat::GB => 1000u64.pow(3),
+      ByteFormat::GiB => 1024u64.pow(3),
+      ByteFormat::TB => 1000u64.pow(4),
+      ByteFormat::TiB => 1024u64.pow(4),
+      ByteFormat::PB => 1000u64.pow(5),
+      ByteFormat::PiB => 1024u64.pow(5),
+      ByteFormat::EB => 1000u64.pow(6),
+      ByteFormat::EiB => 1024u64.pow(6),
+      ByteFormat::Kbit => 1000 / 8,
+      ByteFormat::Mbit => 1000u64.pow(2) / 8,
+      ByteFormat::Gbit => 1000u64.pow(3) / 8,
     }
   }
 
   pub fn convert_to_bytes(&self, val: u64) -> u64 {
-    if let Self::Bytes = self {
-      return val;
-    }
-
-    val * self.as_u64()
+    val.saturating_mul(self.as_u64())
   }
 
   pub fn convert_from_bytes(&self, val: u64) -> f64 {
-    let val = val as f64;
-    if let Self::Bytes = self {
-      return val;
-    }
+    val as f64 / self.as_u64() as f64
+  }
+
+  /// The unit an auto format picks for `bytes` out of the units between `min` and `max`,
+  /// other formats are returned as is
+  pub fn resolve(&self, bytes: u64, min: Option<ByteFormat>, max: Option<ByteFormat>) -> ByteFormat {
+    let units = match self {
+      ByteFormat::Auto => IEC_UNITS,
+      ByteFormat::AutoSi => SI_UNITS,
+      _ => return *self,
+    };
+    let units = units
+      .iter()
+      .filter(|it| min.is_none_or(|min| it.as_u64() >= min.as_u64()))
+      .filter(|it| max.is_none_or(|max| it.as_u64() <= max.as_u64()))
+      .collect::<Vec<_>>();
 
-    val / self.as_u64() as f64
+    units
+      .iter()
+      .rev()
+      .find(|it| bytes >= it.as_u64())
+      .or_else(|| units.first())
+      .map_or(ByteFormat::Bytes, |it| **it)
+  }
+
+  pub fn is_bits(&self) -> bool {
+    matches!(self, ByteFormat::Kbit | ByteFormat::Mbit | ByteFormat::Gbit)
+  }
+
+  /// Widest the number of an auto format can get before it switches to the next unit, like `1023` for `KiB`
+  fn auto_digits(&self) -> Option<usize> {
+    match self {
+      ByteFormat::Auto => Some(4),
+      ByteFormat::AutoSi => Some(3),
+      _ => None,
+    }
   }
 }
 
@@ -55,13 +116,85 @@ impl Display for ByteFormat {
       ByteFormat::GiB => write!(f, "GiB"),
       ByteFormat::TB => write!(f, "TB"),
       ByteFormat::TiB => write!(f, "TiB"),
+      ByteFormat::PB => write!(f, "PB"),
+      ByteFormat::PiB => write!(f, "PiB"),
+      ByteFormat::EB => write!(f, "EB"),
+      ByteFormat::EiB => write!(f, "EiB"),
+      ByteFormat::Kbit => write!(f, "Kbit"),
+      ByteFormat::Mbit => write!(f, "Mbit"),
+      ByteFormat::Gbit => write!(f, "Gbit"),
+      ByteFormat::Auto => write!(f, "auto"),
+      ByteFormat::AutoSi => write!(f, "auto_si"),
     }
   }
 }
 
+impl FromStr for ByteFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "b" => Ok(ByteFormat::Bytes),
+      "kb" => Ok(ByteFormat::KB),
+      "kib" => Ok(ByteFormat::KiB),
+      "mb" => Ok(ByteFormat::MB),
+      "mib" => Ok(ByteFormat::MiB),
+      "gb" => Ok(ByteFormat::GB),
+      "gib" => Ok(ByteFormat::GiB),
+      "tb" => Ok(ByteFormat::TB),
+      "tib" => Ok(ByteFormat::TiB),
+      "pb" => Ok(ByteFormat::PB),
+      "pib" => Ok(ByteFormat::PiB),
+      "eb" => Ok(ByteFormat::EB),
+      "eib" => Ok(ByteFormat::EiB),
+      "kbit" => Ok(ByteFormat::Kbit),
+      "mbit" => Ok(ByteFormat::Mbit),
+      "gbit" => Ok(ByteFormat::Gbit),
+      "auto" => Ok(ByteFormat::Auto),
+      "auto_si" => Ok(ByteFormat::AutoSi),
+      _ => Err(format!("Invalid byte format '{s}', expected b, kb, kib, mb, mib, gb, gib, tb, tib, pb, pib, eb, eib, kbit, mbit, gbit, auto or auto_si")),
+    }
+  }
+}
+
+/// Parses sizes like `512MiB`, `1.5G` or `2 TB` into bytes,
+/// units like `KB` are decimal while `KiB` and single letters like `G` are binary, as in `ls -h`
+pub fn parse_bytes(str: &str) -> Result<u64, String> {
+  let str = str.trim();
+  let split = str
+    .find(|it: char| !(it.is_ascii_digit() || it == '.'))
+    .unwrap_or(str.len());
+  let (number, unit) = str.split_at(split);
+  let number = f64::from_str(number)
+    .map_err(|_| format!("Invalid size '{str}', expected a number with an optional unit like 512MiB or 1.5G"))?;
+  let invalid_unit = || format!("Invalid unit '{}' in size '{str}', expected b, k, m, g, t, p, e or a unit like kb or kib", unit.trim());
+  let unit = match unit.trim().to_lowercase().as_str() {
+    "" | "b" => ByteFormat::Bytes,
+    "k" => ByteFormat::KiB,
+    "m" => ByteFormat::MiB,
+    "g" => ByteFormat::GiB,
+    "t" => ByteFormat::TiB,
+    "p" => ByteFormat::PiB,
+    "e" => ByteFormat::EiB,
+    unit => match ByteFormat::from_str(unit) {
+      Ok(ByteFormat::Auto | ByteFormat::AutoSi) | Err(_) => return Err(invalid_unit()),
+      Ok(unit) => unit,
+    }
+  };
+
+  Ok((number * unit.as_u64() as f64).round() as u64)
+}
+
 pub struct ByteDisplay {
   value: u64,
   format: ByteFormat,
+  /// Smallest unit an auto format may pick
+  min: Option<ByteFormat>,
+  /// Largest unit an auto format may pick
+  max: Option<ByteFormat>,
+  /// Pads auto formats to the widest they can get, so the output doesn't jump around when the unit changes
+  fixed_width: bool,
+  number: NumberFormat,
 }
 
 impl ByteDisplay {
@@ -69,20 +202,55 @@ impl ByteDisplay {
     Self {
       value: from_format.convert_to_bytes(from),
       format: to_format,
+      min: None,
+      max: None,
+      fixed_width: false,
+      number: NumberFormat::default(),
     }
   }
 
+  pub fn with_unit_range(mut self, min: Option<ByteFormat>, max: Option<ByteFormat>) -> Self {
+    self.min = min;
+    self.max = max;
+    self
+  }
+
+  pub fn with_fixed_width(mut self, fixed_width: bool) -> Self {
+    self.fixed_width = fixed_width;
+    self
+  }
+
+  pub fn with_number_format(mut self, number: NumberFormat) -> Self {
+    self.number = number;
+    self
+  }
+
+  /// The unit the value is shown in
+  pub fn unit(&self) -> ByteFormat {
+    self.format.resolve(self.value, self.min, self.max)
+  }
+
   pub fn to_string_no_suffix(&self) -> String {
-    self.format
-      .convert_from_bytes(self.value)
-      .trim_trailing_zeros_with_precision(2)
+    let val = self.number.format(self.unit().convert_from_bytes(self.value));
+
+    match self.format.auto_digits().filter(|_| self.fixed_width) {
+      // digits, the decimal point and the decimals
+      Some(digits) if self.number.precision > 0 => format!("{val:>width$}", width = digits + 1 + self.number.precision),
+      Some(digits) => format!("{val:>digits$}"),
+      None => val,
+    }
   }
 }
 
 impl Display for ByteDisplay {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
     write!(f, "{}", self.to_string_no_suffix())?;
-    write!(f, " {}", self.format)
+
+    match self.format {
+      ByteFormat::Auto if self.fixed_width => write!(f, " {:<3}", self.unit().to_string()),
+      ByteFormat::AutoSi if self.fixed_width => write!(f, " {:<2}", self.unit().to_string()),
+      _ => write!(f, " {}", self.unit()),
+    }
   }
 }
 
@@ -100,3 +268,68 @@ impl ByteFormatConvert for u64 {
     ByteDisplay::from(*self, from_format, to_format)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const UNITS: &[ByteFormat] = &[
+    ByteFormat::Bytes, ByteFormat::KB, ByteFormat::KiB, ByteFormat::MB, ByteFormat::MiB, ByteFormat::GB, ByteFormat::GiB,
+    ByteFormat::TB, ByteFormat::TiB, ByteFormat::PB, ByteFormat::PiB, ByteFormat::EB, ByteFormat::EiB,
+    ByteFormat::Kbit, ByteFormat::Mbit, ByteFormat::Gbit,
+  ];
+
+  #[test]
+  fn round_trip() {
+    for unit in UNITS {
+      // there are no fractions of a byte
+      let values: &[&str] = if *unit == ByteFormat::Bytes { &["1", "3", "15"] } else { &["1", "1.5", "3", "15"] };
+
+      for value in values {
+        let bytes = parse_bytes(&format!("{value}{unit}")).unwrap();
+        let formatted = ByteDisplay::from(bytes, ByteFormat::Bytes, *unit).to_string();
+
+        assert_eq!(formatted, format!("{value} {unit}"));
+        assert_eq!(parse_bytes(&formatted), Ok(bytes), "{}", formatted);
+      }
+    }
+  }
+
+  #[test]
+  fn auto_round_trip() {
+    for bytes in [0, 1, 1023, 1024, 1536, 1024u64.pow(3), 5 * 1024u64.pow(4)] {
+      let formatted = ByteDisplay::from(bytes, ByteFormat::Bytes, ByteFormat::Auto).to_string();
+
+      assert_eq!(parse_bytes(&formatted), Ok(bytes), "{}", formatted);
+    }
+  }
+
+  #[test]
+  fn boundaries() {
+    assert_eq!(ByteFormat::Auto.resolve(1023, None, None), ByteFormat::Bytes);
+    assert_eq!(ByteFormat::Auto.resolve(1024, None, None), ByteFormat::KiB);
+    assert_eq!(ByteFormat::AutoSi.resolve(999, None, None), ByteFormat::Bytes);
+    assert_eq!(ByteFormat::AutoSi.resolve(1000, None, None), ByteFormat::KB);
+    assert_eq!(ByteFormat::Auto.resolve(u64::MAX, None, None), ByteFormat::EiB);
+    assert_eq!(ByteFormat::Auto.resolve(0, Some(ByteFormat::MiB), None), ByteFormat::MiB);
+    assert_eq!(ByteFormat::Auto.resolve(1024u64.pow(4), None, Some(ByteFormat::GiB)), ByteFormat::GiB);
+    assert_eq!(parse_bytes("0"), Ok(0));
+    assert_eq!(parse_bytes("16EiB"), Ok(u64::MAX));
+    assert_eq!(ByteFormat::EiB.convert_to_bytes(u64::MAX), u64::MAX);
+  }
+
+  #[test]
+  fn single_letters_are_binary() {
+    assert_eq!(parse_bytes("1k"), Ok(1024));
+    assert_eq!(parse_bytes("500M"), Ok(500 * 1024u64.pow(2)));
+    assert_eq!(parse_bytes("1.5G"), Ok(3 * 1024u64.pow(3) / 2));
+    assert_eq!(parse_bytes(" 2 TB "), Ok(2 * 1000u64.pow(4)));
+  }
+
+  #[test]
+  fn invalid() {
+    for str in ["", "GiB", "10XB", "10 auto", "10auto_si", "1.2.3G", "-1G", "10 GiB/s"] {
+      assert!(parse_bytes(str).is_err(), "{}", str);
+    }
+  }
+}
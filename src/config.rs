@@ -5,11 +5,15 @@ use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
 use std::io::{BufRead, Read};
+use std::str::FromStr;
 
 #[derive(Default, Debug)]
 pub struct Config {
   pub sections: Vec<(String, String)>,
   pub output: String,
+  /// Everything under the `[settings]` header, these are not placeholders
+  pub settings: HashMap<String, String>,
+  in_settings: bool,
 }
 
 #[derive(Debug)]
@@ -31,7 +35,7 @@ pub type ConfigResult<T> = std::result::Result<T, ConfigError>;
 
 impl Config {
   fn new(sections: Vec<(String, String)>, output: String) -> Self {
-    Self { sections, output }
+    Self { sections, output, ..Default::default() }
   }
 
   pub fn from_str(str: &str) -> ConfigResult<Self> {
@@ -62,13 +66,25 @@ impl Config {
       return Ok(());
     }
 
+    if let Some(header) = line.strip_prefix('[').and_then(|it| it.strip_suffix(']')) {
+      self.in_settings = match header.trim() {
+        "settings" => true,
+        "sections" => false,
+        _ => return Err(ConfigError::InvalidLine(line_num, line.to_string())),
+      };
+
+      return Ok(());
+    }
+
     let (key, value) = line
       .split_once("=")
       .ok_or_else(|| ConfigError::InvalidLine(line_num, line.to_string()))?;
 
     let key_value = (key.trim().to_string(), value.trim().to_string());
 
-    if key_value.0 == "output" {
+    if self.in_settings {
+      self.settings.insert(key_value.0, key_value.1);
+    } else if key_value.0 == "output" {
       self.output = key_value.1
     } else {
       self.sections.push(key_value)
@@ -76,4 +92,17 @@ impl Config {
 
     Ok(())
   }
-}
+
+  /// Gets a setting from the `[settings]` header, invalid values are logged and ignored
+  pub fn setting<T>(&self, key: &str) -> Option<T> where T: FromStr, T::Err: Debug {
+    let value = self.settings.get(key)?;
+
+    match T::from_str(value) {
+      Ok(value) => Some(value),
+      Err(err) => {
+        log::warn!("Invalid value '{value}' for setting '{key}': {err:?}");
+        None
+      }
+    }
+  }
+}
\ No newline at end of file
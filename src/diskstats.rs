@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// `/proc/diskstats` always counts in 512 byte sectors, no matter the device
+const SECTOR_SIZE: u64 = 512;
+
+/// Counters of a block device from `/proc/diskstats`, these only ever go up
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DiskStat {
+  pub name: String,
+  pub reads: u64,
+  pub sectors_read: u64,
+  pub writes: u64,
+  pub sectors_written: u64,
+  /// Milliseconds spent doing I/O
+  pub io_ticks: u64,
+}
+
+impl FromStr for DiskStat {
+  type Err = String;
+
+  /// Parses a line like `259 0 nvme0n1 1000 0 8000 100 500 0 4000 50 0 120 150`
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let fields = s.split_whitespace().collect::<Vec<_>>();
+    let number = |index: usize| fields
+      .get(index)
+      .ok_or_else(|| format!("Missing field {index} in diskstats line '{s}'"))
+      .and_then(|it| u64::from_str(it).map_err(|err| format!("Invalid field {index} in diskstats line '{s}': {err}")));
+
+    Ok(Self {
+      name: fields.get(2).ok_or_else(|| format!("Missing device name in diskstats line '{s}'"))?.to_string(),
+      reads: number(3)?,
+      sectors_read: number(5)?,
+      writes: number(7)?,
+      sectors_written: number(9)?,
+      io_ticks: number(12)?,
+    })
+  }
+}
+
+/// Parses the whole of `/proc/diskstats`, invalid lines are logged and skipped
+pub fn parse(str: &str) -> Vec<DiskStat> {
+  str
+    .lines()
+    .filter(|it| !it.trim().is_empty())
+    .filter_map(|it| DiskStat::from_str(it).map_err(|err| log::warn!("{err}")).ok())
+    .collect()
+}
+
+/// Throughput of a block device between two refreshes
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct DiskRates {
+  pub read_bytes: f64,
+  pub write_bytes: f64,
+  pub read_iops: f64,
+  pub write_iops: f64,
+  /// Percent of the time the device was busy
+  pub util: f64,
+}
+
+impl DiskRates {
+  fn between(previous: &DiskStat, current: &DiskStat, secs: f64) -> Self {
+    let per_sec = |previous: u64, current: u64| current.saturating_sub(previous) as f64 / secs;
+
+    Self {
+      read_bytes: per_sec(previous.sectors_read, current.sectors_read) * SECTOR_SIZE as f64,
+      write_bytes: per_sec(previous.sectors_written, current.sectors_written) * SECTOR_SIZE as f64,
+      read_iops: per_sec(previous.reads, current.reads),
+      write_iops: per_sec(previous.writes, current.writes),
+      util: (per_sec(previous.io_ticks, current.io_ticks) / 10f64).min(100f64),
+    }
+  }
+}
+
+/// Keeps the last two reads of `/proc/diskstats` to compute rates from
+#[derive(Debug)]
+pub struct DiskStats {
+  path: PathBuf,
+  previous: HashMap<String, (Instant, DiskStat)>,
+  current: HashMap<String, (Instant, DiskStat)>,
+}
+
+impl Default for DiskStats {
+  fn default() -> Self {
+    Self::new("/proc/diskstats")
+  }
+}
+
+impl DiskStats {
+  pub fn new(path: impl Into<PathBuf>) -> Self {
+    Self {
+      path: path.into(),
+      previous: HashMap::new(),
+      current: HashMap::new(),
+    }
+  }
+
+  pub fn refresh(&mut self) {
+    match std::fs::read_to_string(&self.path) {
+      Ok(str) => self.update(Instant::now(), parse(&str)),
+      Err(err) => log::warn!("Could not read {:?}: {err}", self.path),
+    }
+  }
+
+  pub fn update(&mut self, time: Instant, stats: Vec<DiskStat>) {
+    let current = stats
+      .into_iter()
+      .map(|it| (it.name.clone(), (time, it)))
+      .collect();
+
+    self.previous = std::mem::replace(&mut self.current, current);
+  }
+
+  /// Rates of a device, which need two refreshes
+  pub fn rates(&self, device: &str) -> Option<DiskRates> {
+    let (previous_time, previous) = self.previous.get(device)?;
+    let (current_time, current) = self.current.get(device)?;
+    let secs = current_time.duration_since(*previous_time).as_secs_f64();
+
+    if secs <= 0f64 {
+      return None;
+    }
+
+    Some(DiskRates::between(previous, current, secs))
+  }
+
+  /// Whole disks, without partitions, loop and ram devices
+  pub fn devices(&self) -> Vec<String> {
+    let mut devices = self.current
+      .keys()
+      .filter(|it| !it.starts_with("loop") && !it.starts_with("ram"))
+      .filter(|it| Path::new("/sys/block").join(it).exists())
+      .cloned()
+      .collect::<Vec<_>>();
+
+    devices.sort();
+    devices
+  }
+
+  /// Rates of a device, or without one summed up over all whole disks with the highest utilization
+  pub fn rates_or_total(&self, device: Option<&str>) -> Option<DiskRates> {
+    if let Some(device) = device {
+      return self.rates(device);
+    }
+
+    self.devices()
+      .iter()
+      .filter_map(|it| self.rates(it))
+      .reduce(|total, it| DiskRates {
+        read_bytes: total.read_bytes + it.read_bytes,
+        write_bytes: total.write_bytes + it.write_bytes,
+        read_iops: total.read_iops + it.read_iops,
+        write_iops: total.write_iops + it.write_iops,
+        util: total.util.max(it.util),
+      })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  const BEFORE: &str = "
+ 259       0 nvme0n1 1000 0 8000 100 500 0 4000 50 0 120 150 0 0 0 0
+ 259       1 nvme0n1p1 10 0 80 1 5 0 40 1 0 2 2 0 0 0 0
+   8       0 sda 4294967290 0 4294967000 0 0 0 0 0 0 0 0
+   8      16 sdb 1 0 8 0 1 0 8 0 0 1 1
+";
+  const AFTER: &str = "
+ 259       0 nvme0n1 1100 0 10048 110 520 0 5024 60 0 620 200 0 0 0 0
+ 259       1 nvme0n1p1 10 0 80 1 5 0 40 1 0 2 2 0 0 0 0
+   8       0 sda 6 0 200 0 0 0 0 0 0 0 0
+";
+
+  fn stats(secs: u64) -> DiskStats {
+    let start = Instant::now();
+    let mut stats = DiskStats::new("/nonexistent");
+
+    stats.update(start, parse(BEFORE));
+    stats.update(start + Duration::from_secs(secs), parse(AFTER));
+    stats
+  }
+
+  #[test]
+  fn parses_lines() {
+    let stats = parse(BEFORE);
+
+    assert_eq!(stats.len(), 4);
+    assert_eq!(stats[0], DiskStat {
+      name: "nvme0n1".to_string(),
+      reads: 1000,
+      sectors_read: 8000,
+      writes: 500,
+      sectors_written: 4000,
+      io_ticks: 120,
+    });
+  }
+
+  #[test]
+  fn skips_invalid_lines() {
+    assert_eq!(parse("8 0 sda 1 2\n8 16 sdb x 0 0 0 0 0 0 0 0 0\n\n").len(), 0);
+  }
+
+  #[test]
+  fn rates() {
+    let rates = stats(2).rates("nvme0n1").unwrap();
+
+    // sectors are always 512 bytes
+    assert_eq!(rates.read_bytes, 2048f64 * 512f64 / 2f64);
+    assert_eq!(rates.write_bytes, 1024f64 * 512f64 / 2f64);
+    assert_eq!(rates.read_iops, 50f64);
+    assert_eq!(rates.write_iops, 10f64);
+    assert_eq!(rates.util, 25f64);
+    assert_eq!(stats(2).rates("nvme0n1p1"), Some(DiskRates::default()));
+  }
+
+  #[test]
+  fn counter_wrap() {
+    assert_eq!(stats(1).rates("sda"), Some(DiskRates::default()));
+  }
+
+  #[test]
+  fn disappeared_device() {
+    assert_eq!(stats(1).rates("sdb"), None);
+    assert_eq!(stats(1).rates("nope"), None);
+  }
+
+  #[test]
+  fn needs_two_samples() {
+    let mut stats = DiskStats::new("/nonexistent");
+
+    stats.update(Instant::now(), parse(BEFORE));
+    assert_eq!(stats.rates("nvme0n1"), None);
+  }
+
+  #[test]
+  fn needs_time_to_pass() {
+    assert_eq!(stats(0).rates("nvme0n1"), None);
+  }
+}
@@ -0,0 +1,237 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Number(f64),
+  /// Metric or function name, with the selector after `@` if there is one
+  Ident(String, Option<String>),
+  Op(char),
+}
+
+fn tokenize(str: &str) -> Result<Vec<Token>, String> {
+  fn take_while(chars: &mut Peekable<Chars>, out: &mut String, f: impl Fn(char) -> bool) {
+    while let Some(char) = chars.next_if(|it| f(*it)) {
+      out.push(char);
+    }
+  }
+
+  let mut out = vec![];
+  let mut chars = str.chars().peekable();
+
+  while let Some(&char) = chars.peek() {
+    match char {
+      char if char.is_whitespace() => {
+        chars.next();
+      }
+      '0'..='9' | '.' => {
+        let mut number = String::new();
+
+        take_while(&mut chars, &mut number, |it| it.is_ascii_digit() || it == '.');
+
+        // sizes like `10GiB` or `500M` are in bytes
+        if chars.peek().is_some_and(|it| it.is_alphabetic()) {
+          take_while(&mut chars, &mut number, |it| it.is_alphabetic());
+
+          // `1e3` would otherwise be 1 EiB followed by 3
+          if chars.peek().is_some_and(|it| it.is_ascii_digit()) {
+            return Err(format!("Unexpected digit after '{number}' in '{str}', exponents like 1e3 aren't supported"));
+          }
+
+          out.push(Token::Number(crate::bytes::parse_bytes(&number)? as f64));
+          continue;
+        }
+
+        out.push(Token::Number(number.parse().map_err(|_| format!("Invalid number '{number}' in '{str}'"))?));
+      }
+      char if char.is_alphabetic() || char == '_' => {
+        let mut name = String::new();
+
+        take_while(&mut chars, &mut name, |it| it.is_alphanumeric() || it == '_');
+
+        let selector = match chars.next_if_eq(&'@') {
+          // quoted selectors can contain anything but quotes, like the mount point `/`
+          Some(_) if chars.next_if_eq(&'"').is_some() => {
+            let mut selector = String::new();
+
+            take_while(&mut chars, &mut selector, |it| it != '"');
+            chars.next().ok_or_else(|| format!("Missing closing quote in '{str}'"))?;
+            Some(selector)
+          }
+          Some(_) => {
+            let mut selector = String::new();
+
+            take_while(&mut chars, &mut selector, |it| it.is_alphanumeric() || "_.:-".contains(it));
+            Some(selector)
+          }
+          None => None,
+        };
+
+        out.push(Token::Ident(name, selector));
+      }
+      '+' | '-' | '*' | '/' | '%' | '(' | ')' | ',' => {
+        chars.next();
+        out.push(Token::Op(char));
+      }
+      char => return Err(format!("Unexpected '{char}' in '{str}'")),
+    }
+  }
+
+  Ok(out)
+}
+
+struct Parser<'a, F> {
+  tokens: Peekable<std::vec::IntoIter<Token>>,
+  metric: &'a F,
+}
+
+impl<'a, F> Parser<'a, F> where F: Fn(&str, Option<&str>) -> Option<f64> {
+  fn next_op(&mut self, ops: &str) -> Option<char> {
+    match self.tokens.peek() {
+      Some(Token::Op(op)) if ops.contains(*op) => {
+        let op = *op;
+
+        self.tokens.next();
+        Some(op)
+      }
+      _ => None,
+    }
+  }
+
+  fn expect(&mut self, op: char) -> Result<(), String> {
+    self.next_op(&op.to_string())
+      .map(|_| ())
+      .ok_or_else(|| format!("Expected '{op}'"))
+  }
+
+  fn sum(&mut self) -> Result<f64, String> {
+    let mut out = self.product()?;
+
+    while let Some(op) = self.next_op("+-") {
+      let rhs = self.product()?;
+
+      out = if op == '+' { out + rhs } else { out - rhs };
+    }
+
+    Ok(out)
+  }
+
+  fn product(&mut self) -> Result<f64, String> {
+    let mut out = self.unary()?;
+
+    while let Some(op) = self.next_op("*/%") {
+      let rhs = self.unary()?;
+
+      out = match op {
+        '*' => out * rhs,
+        '/' => out / rhs,
+        _ => out % rhs,
+      };
+    }
+
+    Ok(out)
+  }
+
+  fn unary(&mut self) -> Result<f64, String> {
+    match self.next_op("-+") {
+      Some('-') => Ok(-self.unary()?),
+      Some(_) => self.unary(),
+      None => self.primary(),
+    }
+  }
+
+  fn primary(&mut self) -> Result<f64, String> {
+    match self.tokens.next() {
+      Some(Token::Number(number)) => Ok(number),
+      Some(Token::Op('(')) => {
+        let out = self.sum()?;
+
+        self.expect(')')?;
+        Ok(out)
+      }
+      Some(Token::Ident(name, None)) if self.next_op("(").is_some() => {
+        let mut args = vec![self.sum()?];
+
+        while self.next_op(",").is_some() {
+          args.push(self.sum()?);
+        }
+
+        self.expect(')')?;
+        call(&name, &args)
+      }
+      // missing values turn the whole result into NaN
+      Some(Token::Ident(name, selector)) => Ok((self.metric)(&name, selector.as_deref()).unwrap_or(f64::NAN)),
+      Some(token) => Err(format!("Unexpected {token:?}")),
+      None => Err("Unexpected end".to_string()),
+    }
+  }
+}
+
+fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+  match (name, args) {
+    ("min", args) => Ok(args.iter().copied().fold(f64::INFINITY, f64::min)),
+    ("max", args) => Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+    ("abs", [value]) => Ok(value.abs()),
+    ("round", [value]) => Ok(value.round()),
+    ("round", [value, digits]) => {
+      let factor = 10f64.powi(*digits as i32);
+
+      Ok((value * factor).round() / factor)
+    }
+    ("abs" | "round", _) => Err(format!("Wrong number of arguments for {name}")),
+    _ => Err(format!("Unknown function '{name}', expected min, max, abs or round")),
+  }
+}
+
+/// Evaluates arithmetic like `mem_used / mem_total * 100` with `+ - * / %`, parentheses and `min`, `max`, `abs` and `round`,
+/// metrics are looked up by name with an optional selector like `cpu_usage@0` or `disk_usage@"/home"`.
+/// Sizes with a unit like `10GiB` are in bytes.
+/// A missing metric or a division by zero makes the result NaN
+pub fn eval(str: &str, metric: &impl Fn(&str, Option<&str>) -> Option<f64>) -> Result<f64, String> {
+  let mut parser = Parser {
+    tokens: tokenize(str)?.into_iter().peekable(),
+    metric,
+  };
+  let out = parser.sum().map_err(|err| format!("{err} in expression '{str}'"))?;
+
+  match parser.tokens.next() {
+    Some(token) => Err(format!("Unexpected {token:?} in expression '{str}'")),
+    None => Ok(out),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn eval_with(str: &str) -> Result<f64, String> {
+    eval(str, &|name, selector| match (name, selector) {
+      ("mem_used", None) => Some(512f64 * 1024f64 * 1024f64),
+      ("disk_free", Some("/")) => Some(5f64 * 1024f64.powi(3)),
+      ("cpu_usage", Some("0")) => Some(40f64),
+      _ => None,
+    })
+  }
+
+  #[test]
+  fn arithmetic() {
+    assert_eq!(eval_with("1 + 2 * 3"), Ok(7f64));
+    assert_eq!(eval_with("(1 + 2) * 3"), Ok(9f64));
+    assert_eq!(eval_with("-2 + 7 % 4"), Ok(1f64));
+    assert_eq!(eval_with("max(1, cpu_usage@0, 3) - min(4, 2)"), Ok(38f64));
+    assert_eq!(eval_with("round(2.345, 2)"), Ok(2.35f64));
+    assert!(eval_with("nope + 1").unwrap().is_nan());
+  }
+
+  #[test]
+  fn sizes() {
+    assert_eq!(eval_with("10GiB"), Ok(10f64 * 1024f64.powi(3)));
+    assert_eq!(eval_with("1.5G"), Ok(1.5f64 * 1024f64.powi(3)));
+    assert_eq!(eval_with("2TB"), Ok(2e12f64));
+    assert_eq!(eval_with("mem_used / 1MiB"), Ok(512f64));
+    assert_eq!(eval_with(r#"disk_free@"/" / 1GiB"#), Ok(5f64));
+    assert!(eval_with("10XB").is_err());
+    assert!(eval_with("1e3").unwrap_err().contains("exponents"));
+    assert!(eval_with("2G5").is_err());
+  }
+}
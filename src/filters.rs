@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+  Left,
+  Right,
+  Center,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Case {
+  Upper,
+  Lower,
+}
+
+/// Filters any placeholder can take, applied to its value in the order case, truncation and padding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextFilters {
+  case: Option<Case>,
+  /// Maximum display width
+  trunc: Option<usize>,
+  /// Ends truncated values with `…`
+  ellipsis: bool,
+  /// Minimum display width
+  pad: Option<usize>,
+  align: Align,
+}
+
+impl Default for TextFilters {
+  fn default() -> Self {
+    Self {
+      case: None,
+      trunc: None,
+      ellipsis: false,
+      pad: None,
+      align: Align::Left,
+    }
+  }
+}
+
+impl TextFilters {
+  pub fn from_args(args: &[&str]) -> Self {
+    let mut out = Self::default();
+    let width = |value: &str| usize::from_str(value).map_err(|err| log::warn!("Invalid width '{value}': {err}")).ok();
+
+    for arg in args {
+      match arg.split_once('=') {
+        Some(("trunc", value)) => out.trunc = width(value),
+        Some(("pad", value)) => out.pad = width(value),
+        _ => {}
+      }
+
+      match *arg {
+        "upper" => out.case = Some(Case::Upper),
+        "lower" => out.case = Some(Case::Lower),
+        "ellipsis" => out.ellipsis = true,
+        "left" => out.align = Align::Left,
+        "right" => out.align = Align::Right,
+        "center" => out.align = Align::Center,
+        _ => {}
+      }
+    }
+
+    out
+  }
+
+  pub fn apply(&self, value: String) -> String {
+    let value = match self.case {
+      Some(case) => change_case(&value, case),
+      None => value,
+    };
+    let value = match self.trunc {
+      Some(trunc) if display_width(&value) > trunc => truncate(&value, trunc, self.ellipsis),
+      _ => value,
+    };
+    let padding = self.pad.unwrap_or(0).saturating_sub(display_width(&value));
+
+    match self.align {
+      _ if padding == 0 => value,
+      Align::Left => format!("{value}{}", " ".repeat(padding)),
+      Align::Right => format!("{}{value}", " ".repeat(padding)),
+      Align::Center => format!("{}{value}{}", " ".repeat(padding / 2), " ".repeat(padding - padding / 2)),
+    }
+  }
+}
+
+/// Splits a string into ANSI escape sequences and visible characters
+fn segments(str: &str) -> impl Iterator<Item=(&str, bool)> {
+  let mut rest = str;
+
+  std::iter::from_fn(move || {
+    let first = rest.chars().next()?;
+    let len = match rest.strip_prefix("\x1b[") {
+      // CSI sequences end with a byte from `@` to `~`
+      Some(csi) => csi
+        .find(|it: char| ('@'..='~').contains(&it))
+        .map_or(rest.len(), |it| it + 3),
+      None => first.len_utf8(),
+    };
+    let (segment, remaining) = rest.split_at(len);
+
+    rest = remaining;
+    Some((segment, first != '\x1b'))
+  })
+}
+
+/// Changes the case of visible characters only, escape sequences like `\x1b[31m` would break otherwise
+fn change_case(str: &str, case: Case) -> String {
+  segments(str)
+    .map(|(segment, visible)| match (visible, case) {
+      (false, _) => segment.to_string(),
+      (true, Case::Upper) => segment.to_uppercase(),
+      (true, Case::Lower) => segment.to_lowercase(),
+    })
+    .collect()
+}
+
+/// Columns a string takes up in a terminal, without escape sequences
+pub fn display_width(str: &str) -> usize {
+  segments(str)
+    .filter(|(_, visible)| *visible)
+    .filter_map(|(it, _)| it.chars().next()?.width())
+    .sum()
+}
+
+/// Cuts visible characters after `width` columns, escape sequences are kept so colors still get reset
+fn truncate(str: &str, width: usize, ellipsis: bool) -> String {
+  let width = if ellipsis { width.saturating_sub(1) } else { width };
+  let mut out = String::with_capacity(str.len());
+  let mut used = 0;
+  let mut cut = false;
+
+  for (segment, visible) in segments(str) {
+    if !visible {
+      out.push_str(segment);
+      continue;
+    }
+
+    let char_width = segment.chars().next().and_then(|it| it.width()).unwrap_or(0);
+
+    if cut || used + char_width > width {
+      if !cut && ellipsis {
+        out.push('…');
+      }
+
+      cut = true;
+      continue;
+    }
+
+    used += char_width;
+    out.push_str(segment);
+  }
+
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn apply(args: &[&str], value: &str) -> String {
+    TextFilters::from_args(args).apply(value.to_string())
+  }
+
+  #[test]
+  fn case_keeps_escapes() {
+    assert_eq!(apply(&["upper"], "\x1b[31mhi\x1b[0m"), "\x1b[31mHI\x1b[0m");
+    assert_eq!(apply(&["lower"], "\x1b[1;32mHI"), "\x1b[1;32mhi");
+  }
+
+  #[test]
+  fn width() {
+    assert_eq!(display_width("\x1b[31m日本\x1b[0m"), 4);
+    assert_eq!(apply(&["trunc=3", "ellipsis"], "abcdef"), "ab…");
+    assert_eq!(apply(&["pad=4", "right"], "\x1b[31mab"), "  \x1b[31mab");
+  }
+}
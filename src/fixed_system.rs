@@ -3,20 +3,70 @@
 use std::fs::File;
 use std::io::Read;
 use std::ops::{Deref, DerefMut};
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
-use sysinfo::{ComponentExt, RefreshKind, System, SystemExt};
+use regex::Regex;
+use sysinfo::{ComponentExt, DiskExt, NetworkData, NetworksExt, ProcessorExt, RefreshKind, System, SystemExt};
+
+use crate::diskstats::{DiskRates, DiskStats};
+use crate::net;
+
+/// Labels of CPU temperature sensors, newer kernels and `sensors` prefix them with the driver name
+const AMD_SENSORS: &[&str] = &[r"^(k10temp |zenpower )?Tctl$", r"^(k10temp |zenpower )?Tdie$"];
+const INTEL_SENSORS: &[&str] = &[r"^(coretemp )?Package id 0$", r"^(coretemp )?Physical id 0$"];
+const GENERIC_SENSORS: &[&str] = &[r"^CPU$", r"^cpu[_ ]thermal", r"^x86_pkg_temp", r"^acpitz"];
+
+/// Built-in CPU temperature sensors for a vendor id, with generic ones last
+fn fallback_sensors(vendor: &str) -> &'static [Regex] {
+  static AMD: OnceLock<Vec<Regex>> = OnceLock::new();
+  static INTEL: OnceLock<Vec<Regex>> = OnceLock::new();
+  static GENERIC: OnceLock<Vec<Regex>> = OnceLock::new();
+
+  fn compile(vendor: &[&str]) -> Vec<Regex> {
+    vendor
+      .iter()
+      .chain(GENERIC_SENSORS)
+      .filter_map(|it| Regex::new(it).ok())
+      .collect()
+  }
+
+  match vendor {
+    "AuthenticAMD" | "HygonGenuine" => AMD.get_or_init(|| compile(AMD_SENSORS)),
+    "GenuineIntel" => INTEL.get_or_init(|| compile(INTEL_SENSORS)),
+    _ => GENERIC.get_or_init(|| compile(&[])),
+  }
+}
+
+/// Index of the first label matching `custom`, otherwise the built-in sensors for `vendor` in order
+fn find_sensor<'a>(labels: impl Iterator<Item=&'a str> + Clone, custom: Option<&Regex>, vendor: &str) -> Option<usize> {
+  custom
+    .into_iter()
+    .chain(fallback_sensors(vendor))
+    .find_map(|sensor| labels.clone().position(|it| sensor.is_match(it)))
+}
 
 /// Removes [sysinfo::System] memory conversion, since it makes it inaccurate
 /// `/proc/meminfo` is already `KiB`, even though it says `kB`
 #[derive(Default, Debug)]
 pub struct FixedSystem {
   sys: System,
+  refreshes: RefreshKind,
+  /// Labels that count as CPU temperature, before the built-in ones
+  cpu_temp_sensor: Option<Regex>,
+  /// I/O counters, refreshed along with disks
+  diskstats: DiskStats,
+  last_refresh: Option<Instant>,
+  /// Time between the last two refreshes, which network counters are relative to
+  refresh_interval: Option<Duration>,
 }
 
 pub struct CPU<'a> {
   pub processor: &'a sysinfo::Processor,
-  pub component: &'a sysinfo::Component
+  /// Temperature sensor, if there is one
+  pub component: Option<&'a sysinfo::Component>,
 }
 
 impl Deref for FixedSystem {
@@ -46,26 +96,110 @@ impl FixedSystem {
   }
 
   pub fn new_all() -> Self {
-    Self {
-      sys: System::new_all(),
-      ..Default::default()
-    }
+    Self::new_with_specifics(RefreshKind::everything())
   }
 
   pub fn new_with_specifics(refreshes: RefreshKind) -> Self {
+    let mut diskstats = DiskStats::default();
+
+    if refreshes.disks() {
+      diskstats.refresh();
+    }
+
     Self {
       sys: System::new_with_specifics(refreshes),
+      refreshes,
+      diskstats,
+      last_refresh: Some(Instant::now()),
       ..Default::default()
     }
   }
 
-  pub fn cpu(&self) -> Option<CPU<'_>> {
-    Some(CPU {
+  /// Refreshes everything this was created with
+  pub fn refresh(&mut self) {
+    let now = Instant::now();
+
+    self.sys.refresh_specifics(self.refreshes);
+    self.refresh_interval = self.last_refresh.map(|it| now.duration_since(it));
+    self.last_refresh = Some(now);
+
+    if self.refreshes.disks() {
+      self.diskstats.refresh();
+    }
+  }
+
+  pub fn set_cpu_temp_sensor(&mut self, sensor: Option<Regex>) {
+    self.cpu_temp_sensor = sensor;
+  }
+
+  pub fn cpu(&self) -> CPU<'_> {
+    CPU {
       processor: self.sys.global_processor_info(),
-      component: self.components()
-        .iter()
-        .find(|it| it.label() == "CPU")?
-    })
+      component: self.cpu_temp_component(),
+    }
+  }
+
+  /// First component matching `cpu_temp_sensor`, otherwise the built-in sensors for the CPU's vendor
+  pub fn cpu_temp_component(&self) -> Option<&sysinfo::Component> {
+    let components = self.components();
+    let vendor = self.sys.global_processor_info().vendor_id();
+
+    let index = find_sensor(components.iter().map(|it| it.label()), self.cpu_temp_sensor.as_ref(), vendor)?;
+
+    components.get(index)
+  }
+
+  /// Disk mounted at `mount`, or at `/` without one
+  pub fn disk(&self, mount: Option<&str>) -> Option<&sysinfo::Disk> {
+    let mount = Path::new(mount.unwrap_or("/"));
+
+    self.disks()
+      .iter()
+      .find(|it| it.mount_point() == mount)
+  }
+
+  /// I/O rates of a block device like `nvme0n1`, or of all disks without one
+  pub fn disk_rates(&self, device: Option<&str>) -> Option<DiskRates> {
+    self.diskstats.rates_or_total(device)
+  }
+
+  /// Whole disks that have I/O rates
+  pub fn disk_devices(&self) -> Vec<String> {
+    self.diskstats.devices()
+  }
+
+  /// Interfaces named `iface`, or every physical one without a name
+  pub fn interfaces(&self, iface: Option<&str>) -> Vec<(&str, &NetworkData)> {
+    self.networks()
+      .iter()
+      .filter(|(name, _)| match iface {
+        Some(iface) => name.as_str() == iface,
+        None => !net::is_virtual(name),
+      })
+      .map(|(name, data)| (name.as_str(), data))
+      .collect()
+  }
+
+  /// Sum of a counter over the selected interfaces, `None` when there are none
+  pub fn network_total(&self, iface: Option<&str>, f: fn(&NetworkData) -> u64) -> Option<u64> {
+    let interfaces = self.interfaces(iface);
+
+    if interfaces.is_empty() {
+      return None;
+    }
+
+    Some(interfaces.iter().map(|(_, data)| f(data)).sum())
+  }
+
+  /// Per second rate of a counter that sysinfo keeps relative to the last refresh
+  pub fn network_rate(&self, iface: Option<&str>, f: fn(&NetworkData) -> u64) -> Option<f64> {
+    let secs = self.refresh_interval?.as_secs_f64();
+
+    if secs <= 0f64 {
+      return None;
+    }
+
+    Some(self.network_total(iface, f)? as f64 / secs)
   }
 
   /// Total Memory in `KiB`
@@ -103,3 +237,27 @@ impl FixedSystem {
     convert(self.sys.used_swap())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sensor_priority() {
+    let labels = ["acpitz temp1", "coretemp Core 0", "coretemp Package id 0", "k10temp Tdie", "k10temp Tctl"];
+    let find = |custom: Option<&str>, vendor| {
+      let custom = custom.map(|it| Regex::new(it).unwrap());
+
+      find_sensor(labels.iter().copied(), custom.as_ref(), vendor).map(|it| labels[it])
+    };
+
+    assert_eq!(find(None, "GenuineIntel"), Some("coretemp Package id 0"));
+    assert_eq!(find(None, "AuthenticAMD"), Some("k10temp Tctl"));
+    assert_eq!(find(None, "HygonGenuine"), Some("k10temp Tctl"));
+    assert_eq!(find(None, "Unknown"), Some("acpitz temp1"));
+    assert_eq!(find(Some("Core 0$"), "GenuineIntel"), Some("coretemp Core 0"));
+    // an override that matches nothing falls back to the built-in sensors
+    assert_eq!(find(Some("^nvme"), "AuthenticAMD"), Some("k10temp Tctl"));
+    assert_eq!(find_sensor(std::iter::once("nvme Composite"), None, "GenuineIntel"), None);
+  }
+}
@@ -0,0 +1,181 @@
+use crate::color::Color;
+
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Braille dots per column from bottom to top, left column first
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x40, 0x04, 0x02, 0x01], [0x80, 0x20, 0x10, 0x08]];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Scale {
+  pub lo: f64,
+  pub hi: f64,
+}
+
+impl Scale {
+  pub fn new(lo: f64, hi: f64) -> Self {
+    Self { lo, hi }
+  }
+
+  /// Scale from the smallest to the largest value, so flat lines still show something
+  pub fn fit(values: &[f64]) -> Self {
+    let lo = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    if lo.is_finite() && hi.is_finite() {
+      Self::new(lo.min(0f64), hi)
+    } else {
+      Self::new(0f64, 1f64)
+    }
+  }
+
+  /// Where the value is within the scale from `0` to `1`
+  pub fn fraction(&self, value: f64) -> f64 {
+    if self.hi <= self.lo || !value.is_finite() {
+      return 0f64;
+    }
+
+    ((value - self.lo) / (self.hi - self.lo)).clamp(0f64, 1f64)
+  }
+}
+
+/// Colors graphs green, yellow and red depending on which threshold a value reached
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Thresholds {
+  pub warn: Option<f64>,
+  pub crit: Option<f64>,
+}
+
+impl Thresholds {
+  pub fn is_empty(&self) -> bool {
+    self.warn.is_none() && self.crit.is_none()
+  }
+
+  pub fn color(&self, value: f64) -> Option<Color> {
+    if self.is_empty() {
+      return None;
+    }
+
+    match (self.warn, self.crit) {
+      (_, Some(crit)) if value >= crit => Some(Color::Red),
+      (Some(warn), _) if value >= warn => Some(Color::Yellow),
+      _ => Some(Color::Green),
+    }
+  }
+}
+
+/// Resets the color if one was emitted, so whatever follows a graph isn't colored like it
+fn reset(out: &mut String, last: Option<Color>) {
+  if last.is_some() {
+    out.push_str(&Color::Reset.fg());
+  }
+}
+
+/// Pushes `str` colored by the thresholds, only emitting a color when it changes
+fn push_colored(out: &mut String, last: &mut Option<Color>, color: Option<Color>, str: &str) {
+  if let Some(color) = color.filter(|it| Some(*it) != *last) {
+    out.push_str(&color.fg());
+    *last = Some(color);
+  }
+
+  out.push_str(str);
+}
+
+/// Block sparkline of the last `width` values, padded with spaces on the left
+pub fn sparkline(values: &[f64], scale: Scale, width: usize, thresholds: Thresholds) -> String {
+  let values = &values[values.len().saturating_sub(width)..];
+  let mut out = " ".repeat(width - values.len());
+  let mut last = None;
+
+  for value in values {
+    let level = (scale.fraction(*value) * (BLOCKS.len() - 1) as f64).round() as usize;
+
+    push_colored(&mut out, &mut last, thresholds.color(*value), &BLOCKS[level].to_string());
+  }
+
+  reset(&mut out, last);
+  out
+}
+
+/// Braille graph of the last `width * 2` values, every character holds two values with four levels each
+pub fn braille(values: &[f64], scale: Scale, width: usize, thresholds: Thresholds) -> String {
+  let values = &values[values.len().saturating_sub(width * 2)..];
+  // pads on the left so the newest value is always in the last column
+  let padding = width * 2 - values.len();
+  let mut out = String::with_capacity(width * 3);
+  let mut last = None;
+
+  for char in 0..width {
+    let mut dots = 0u32;
+    let mut highest = None;
+
+    for (column, column_dots) in BRAILLE_DOTS.iter().enumerate() {
+      let index = match (char * 2 + column).checked_sub(padding) {
+        Some(index) => index,
+        None => continue,
+      };
+      let value = values[index];
+      let level = (scale.fraction(value) * 4f64).round() as usize;
+
+      // always show at least the bottom dot, so the graph doesn't disappear when it's idle
+      for dot in column_dots.iter().take(level.max(1)) {
+        dots |= dot;
+      }
+
+      highest = Some(highest.map_or(value, |it: f64| it.max(value)));
+    }
+
+    let char = std::char::from_u32(0x2800 + dots).unwrap_or(' ');
+
+    match highest {
+      Some(value) => push_colored(&mut out, &mut last, thresholds.color(value), &char.to_string()),
+      None => out.push(' '),
+    }
+  }
+
+  reset(&mut out, last);
+  out
+}
+
+/// Proportional bar, when thresholds are given the filled part is colored and the empty part is dimmed
+pub fn bar(value: f64, scale: Scale, width: usize, fill: &str, empty: &str, thresholds: Thresholds) -> String {
+  let filled = (scale.fraction(value) * width as f64).round() as usize;
+  let mut out = String::with_capacity(width * 4);
+  let color = thresholds.color(value);
+
+  if let Some(color) = color {
+    out.push_str(&color.fg());
+  }
+
+  out.push_str(&fill.repeat(filled));
+
+  if color.is_some() {
+    out.push_str(&Color::BrightBlack.fg());
+  }
+
+  out.push_str(&empty.repeat(width - filled));
+  reset(&mut out, color);
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SCALE: Scale = Scale { lo: 0f64, hi: 100f64 };
+  const THRESHOLDS: Thresholds = Thresholds { warn: Some(50f64), crit: Some(90f64) };
+
+  #[test]
+  fn resets_colors() {
+    let reset = Color::Reset.fg();
+
+    assert!(sparkline(&[10f64, 95f64], SCALE, 2, THRESHOLDS).ends_with(&reset));
+    assert!(braille(&[10f64, 60f64], SCALE, 1, THRESHOLDS).ends_with(&reset));
+    assert!(bar(60f64, SCALE, 4, "#", "-", THRESHOLDS).ends_with(&reset));
+  }
+
+  #[test]
+  fn no_colors_without_thresholds() {
+    assert_eq!(sparkline(&[0f64, 100f64], SCALE, 3, Thresholds::default()), " ▁█");
+    assert_eq!(bar(50f64, SCALE, 4, "#", "-", Thresholds::default()), "##--");
+  }
+}
@@ -0,0 +1,489 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::util::parse_duration;
+
+/// Seconds since [UNIX_EPOCH]
+pub fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|it| it.as_secs())
+    .unwrap_or_default()
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Aggregate {
+  Min,
+  Max,
+  Avg,
+  /// Newest minus oldest value
+  Delta,
+}
+
+impl Aggregate {
+  /// Values have to be ordered from oldest to newest
+  pub fn apply(&self, values: impl IntoIterator<Item=f64>) -> Option<f64> {
+    let mut count = 0usize;
+    let mut first = 0f64;
+    let mut out = match self {
+      Aggregate::Min => f64::INFINITY,
+      Aggregate::Max => f64::NEG_INFINITY,
+      Aggregate::Avg | Aggregate::Delta => 0f64,
+    };
+
+    for value in values {
+      if count == 0 {
+        first = value;
+      }
+
+      count += 1;
+      out = match self {
+        Aggregate::Min => out.min(value),
+        Aggregate::Max => out.max(value),
+        Aggregate::Avg => out + value,
+        Aggregate::Delta => value - first,
+      };
+    }
+
+    match (self, count) {
+      (_, 0) => None,
+      (Aggregate::Avg, count) => Some(out / count as f64),
+      _ => Some(out),
+    }
+  }
+}
+
+impl FromStr for Aggregate {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "min" => Ok(Aggregate::Min),
+      "max" => Ok(Aggregate::Max),
+      "avg" | "mean" => Ok(Aggregate::Avg),
+      "delta" => Ok(Aggregate::Delta),
+      _ => Err(format!("Invalid aggregate '{s}', expected min, max, avg or delta")),
+    }
+  }
+}
+
+/// Recent samples of every metric, kept in memory between refreshes
+#[derive(Debug)]
+pub struct RollingHistory {
+  /// Samples older than this are dropped
+  keep: Duration,
+  samples: HashMap<String, VecDeque<(Instant, f64)>>,
+}
+
+impl RollingHistory {
+  pub fn new(keep: Duration) -> Self {
+    Self {
+      keep,
+      samples: HashMap::new(),
+    }
+  }
+
+  pub fn push(&mut self, metric: &str, time: Instant, value: f64) {
+    let keep = self.keep;
+    let samples = self.samples
+      .entry(metric.to_string())
+      .or_default();
+
+    samples.push_back((time, value));
+
+    while let Some((oldest, _)) = samples.front() {
+      match time.checked_duration_since(*oldest) {
+        Some(age) if age > keep => samples.pop_front(),
+        _ => break,
+      };
+    }
+  }
+
+  /// Values of a metric within the window from oldest to newest,
+  /// without a window only the last two samples are returned
+  pub fn values(&self, metric: &str, window: Option<Duration>) -> Vec<f64> {
+    let window = match window {
+      Some(window) => window,
+      None => return self.last(metric, 2),
+    };
+
+    match self.samples.get(metric) {
+      Some(samples) => samples
+        .iter()
+        .filter(|(time, _)| time.elapsed() <= window)
+        .map(|(_, value)| *value)
+        .collect(),
+      None => vec![],
+    }
+  }
+
+  /// The last `count` values of a metric from oldest to newest
+  pub fn last(&self, metric: &str, count: usize) -> Vec<f64> {
+    match self.samples.get(metric) {
+      Some(samples) => samples
+        .iter()
+        .skip(samples.len().saturating_sub(count))
+        .map(|(_, value)| *value)
+        .collect(),
+      None => vec![],
+    }
+  }
+
+  /// If the oldest sample of a metric is at least as old as the window
+  pub fn covers(&self, metric: &str, window: Duration) -> bool {
+    self.samples
+      .get(metric)
+      .and_then(|it| it.front())
+      .map(|(time, _)| time.elapsed() >= window)
+      .unwrap_or(false)
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+  /// Samples older than this are dropped
+  pub retention: Duration,
+  /// `(age, bucket)` pairs, samples older than `age` get averaged into `bucket` sized buckets
+  pub downsample: Vec<(Duration, Duration)>,
+}
+
+impl Default for RetentionPolicy {
+  fn default() -> Self {
+    Self {
+      retention: Duration::from_secs(7 * 24 * 60 * 60),
+      downsample: vec![
+        (Duration::from_secs(60 * 60), Duration::from_secs(60)),
+        (Duration::from_secs(24 * 60 * 60), Duration::from_secs(15 * 60)),
+      ],
+    }
+  }
+}
+
+impl RetentionPolicy {
+  /// Parses downsample policies like `1h:1m, 1d:15m`
+  pub fn parse_downsample(str: &str) -> Result<Vec<(Duration, Duration)>, String> {
+    str
+      .split(',')
+      .filter(|it| !it.trim().is_empty())
+      .map(|it| {
+        let (age, bucket) = it
+          .split_once(':')
+          .ok_or_else(|| format!("Invalid downsample policy '{it}', expected age:bucket"))?;
+
+        Ok((parse_duration(age)?, parse_duration(bucket)?))
+      })
+      .collect()
+  }
+
+  /// Bucket size in seconds for a sample of this age, if it should be downsampled
+  fn bucket(&self, age: u64) -> Option<u64> {
+    self.downsample
+      .iter()
+      .filter(|(min_age, _)| age >= min_age.as_secs())
+      .max_by_key(|(min_age, _)| *min_age)
+      .map(|(_, bucket)| bucket.as_secs().max(1))
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+  /// Seconds since [UNIX_EPOCH]
+  pub time: u64,
+  pub metric: String,
+  pub value: f64,
+  /// Samples averaged into this one, so averaging it again weighs it by them
+  pub count: u32,
+}
+
+/// Append-only binary log of samples,
+/// every record is `time: u64, count: u32, metric length: u8, metric: [u8], value: f64` in little endian
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+  path: PathBuf,
+}
+
+impl HistoryStore {
+  pub fn open(path: impl Into<PathBuf>) -> Self {
+    Self { path: path.into() }
+  }
+
+  /// `$XDG_DATA_HOME/sysusage/history.bin` falling back to `~/.local/share`
+  pub fn default_path() -> PathBuf {
+    let data = std::env::var_os("XDG_DATA_HOME")
+      .map(PathBuf::from)
+      .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+      .unwrap_or_default();
+
+    data.join("sysusage").join("history.bin")
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub fn append(&self, time: u64, samples: &[(String, f64)]) -> std::io::Result<()> {
+    if let Some(parent) = self.path.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+
+    let file = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&self.path)?;
+    let mut out = BufWriter::new(file);
+
+    for (metric, value) in samples {
+      write_sample(&mut out, &Sample { time, metric: metric.clone(), value: *value, count: 1 })?;
+    }
+
+    out.flush()
+  }
+
+  /// Reads every sample, a missing store is empty
+  pub fn read(&self) -> std::io::Result<Vec<Sample>> {
+    let file = match File::open(&self.path) {
+      Ok(file) => file,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+      Err(err) => return Err(err),
+    };
+
+    let mut buf = BufReader::new(file);
+    let mut out = vec![];
+
+    // a truncated record at the end means a write was interrupted, so it's ignored
+    while let Some(sample) = read_sample(&mut buf)? {
+      out.push(sample);
+    }
+
+    Ok(out)
+  }
+
+  /// `(time, value)` of a metric since `since` in seconds since [UNIX_EPOCH].
+  /// This reads the whole store no matter how short the window is, compaction keeps that
+  /// to one sample per bucket for anything older than the first downsample age
+  pub fn query(&self, metric: &str, since: u64) -> std::io::Result<Vec<(u64, f64)>> {
+    Ok(self.read()?
+      .into_iter()
+      .filter(|it| it.time >= since && it.metric == metric)
+      .map(|it| (it.time, it.value))
+      .collect())
+  }
+
+  /// Drops samples older than the retention and averages old samples into buckets,
+  /// weighted by how many samples they already are an average of
+  pub fn compact(&self, now: u64, policy: &RetentionPolicy) -> std::io::Result<()> {
+    let oldest = now.saturating_sub(policy.retention.as_secs());
+    let mut kept = vec![];
+    let mut buckets = BTreeMap::<(u64, String), (f64, u32)>::new();
+
+    for sample in self.read()? {
+      if sample.time < oldest {
+        continue;
+      }
+
+      match policy.bucket(now.saturating_sub(sample.time)) {
+        Some(size) => {
+          let bucket = buckets
+            .entry((sample.time - sample.time % size, sample.metric))
+            .or_insert((0f64, 0));
+
+          bucket.0 += sample.value * sample.count as f64;
+          bucket.1 = bucket.1.saturating_add(sample.count);
+        }
+        None => kept.push(sample),
+      }
+    }
+
+    let buckets = buckets
+      .into_iter()
+      .map(|((time, metric), (sum, count))| Sample { time, metric, value: sum / count as f64, count });
+
+    self.rewrite(buckets.chain(kept))
+  }
+
+  /// Replaces the store with these samples, written to a temporary file first so it never ends up half written
+  fn rewrite(&self, samples: impl IntoIterator<Item=Sample>) -> std::io::Result<()> {
+    let tmp = self.path.with_extension("tmp");
+    let mut out = BufWriter::new(File::create(&tmp)?);
+
+    for sample in samples {
+      write_sample(&mut out, &sample)?;
+    }
+
+    out.flush()?;
+    drop(out);
+
+    std::fs::rename(tmp, &self.path)
+  }
+}
+
+fn write_sample(out: &mut impl Write, sample: &Sample) -> std::io::Result<()> {
+  let metric = &sample.metric.as_bytes()[..sample.metric.len().min(u8::MAX as usize)];
+
+  out.write_all(&sample.time.to_le_bytes())?;
+  out.write_all(&sample.count.to_le_bytes())?;
+  out.write_all(&[metric.len() as u8])?;
+  out.write_all(metric)?;
+  out.write_all(&sample.value.to_le_bytes())
+}
+
+fn read_sample(buf: &mut impl Read) -> std::io::Result<Option<Sample>> {
+  fn eof_as_none<T>(result: std::io::Result<T>) -> std::io::Result<Option<T>> {
+    match result {
+      Ok(value) => Ok(Some(value)),
+      Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+      Err(err) => Err(err),
+    }
+  }
+
+  let mut time = [0u8; 8];
+  let mut count = [0u8; 4];
+  let mut len = [0u8; 1];
+  let mut value = [0u8; 8];
+
+  if eof_as_none(buf.read_exact(&mut time))?.is_none()
+    || eof_as_none(buf.read_exact(&mut count))?.is_none()
+    || eof_as_none(buf.read_exact(&mut len))?.is_none() {
+    return Ok(None);
+  }
+
+  let mut metric = vec![0u8; len[0] as usize];
+
+  if eof_as_none(buf.read_exact(&mut metric))?.is_none()
+    || eof_as_none(buf.read_exact(&mut value))?.is_none() {
+    return Ok(None);
+  }
+
+  Ok(Some(Sample {
+    time: u64::from_le_bytes(time),
+    metric: String::from_utf8_lossy(&metric).into_owned(),
+    value: f64::from_le_bytes(value),
+    count: u32::from_le_bytes(count),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn store(name: &str) -> HistoryStore {
+    let path = std::env::temp_dir().join(format!("sysusage-{}-{}.bin", std::process::id(), name));
+
+    let _ = std::fs::remove_file(&path);
+    HistoryStore::open(path)
+  }
+
+  fn policy(downsample: &str) -> RetentionPolicy {
+    RetentionPolicy {
+      retention: Duration::from_secs(7 * 24 * 60 * 60),
+      downsample: RetentionPolicy::parse_downsample(downsample).unwrap(),
+    }
+  }
+
+  #[test]
+  fn aggregates() {
+    let values = vec![3f64, 1f64, 4f64, 2f64];
+
+    assert_eq!(Aggregate::Min.apply(values.clone()), Some(1f64));
+    assert_eq!(Aggregate::Max.apply(values.clone()), Some(4f64));
+    assert_eq!(Aggregate::Avg.apply(values.clone()), Some(2.5));
+    assert_eq!(Aggregate::Delta.apply(values), Some(-1f64));
+    assert_eq!(Aggregate::Delta.apply(vec![5f64]), Some(0f64));
+    assert_eq!(Aggregate::Avg.apply(vec![]), None);
+    assert_eq!(Aggregate::Delta.apply(vec![]), None);
+  }
+
+  #[test]
+  fn rolling_window() {
+    let now = Instant::now();
+    let ago = |secs| now - Duration::from_secs(secs);
+    let mut history = RollingHistory::new(Duration::from_secs(60));
+
+    history.push("a", ago(70), 1f64);
+    history.push("a", ago(50), 2f64);
+    history.push("a", ago(20), 3f64);
+    // evicted relative to the newest sample, not to now
+    assert_eq!(history.values("a", Some(Duration::from_secs(3600))), vec![1f64, 2f64, 3f64]);
+
+    history.push("a", now, 4f64);
+    assert_eq!(history.values("a", Some(Duration::from_secs(3600))), vec![2f64, 3f64, 4f64]);
+    assert_eq!(history.values("a", Some(Duration::from_secs(30))), vec![3f64, 4f64]);
+    assert_eq!(history.values("a", None), vec![3f64, 4f64]);
+    assert_eq!(history.values("b", None), Vec::<f64>::new());
+  }
+
+  #[test]
+  fn rolling_last_and_covers() {
+    let now = Instant::now();
+    let mut history = RollingHistory::new(Duration::from_secs(60));
+
+    history.push("a", now - Duration::from_secs(30), 1f64);
+    history.push("a", now, 2f64);
+
+    assert_eq!(history.last("a", 1), vec![2f64]);
+    assert_eq!(history.last("a", 5), vec![1f64, 2f64]);
+    assert_eq!(history.last("b", 5), Vec::<f64>::new());
+    assert!(history.covers("a", Duration::from_secs(20)));
+    assert!(!history.covers("a", Duration::from_secs(40)));
+    assert!(!history.covers("b", Duration::ZERO));
+  }
+
+  #[test]
+  fn round_trip() {
+    let store = store("round-trip");
+
+    store.append(10, &[("a".to_string(), 1.5), ("b".to_string(), 2f64)]).unwrap();
+    store.append(20, &[("a".to_string(), 3f64)]).unwrap();
+
+    assert_eq!(store.query("a", 0).unwrap(), vec![(10, 1.5), (20, 3f64)]);
+    assert_eq!(store.query("a", 15).unwrap(), vec![(20, 3f64)]);
+    std::fs::remove_file(store.path()).unwrap();
+  }
+
+  #[test]
+  fn repeated_compaction_keeps_the_average() {
+    let store = store("compaction");
+
+    // three samples in the first minute, one in the second
+    for (time, value) in [(0, 1f64), (10, 2f64), (20, 3f64), (60, 10f64)] {
+      store.append(time, &[("a".to_string(), value)]).unwrap();
+    }
+
+    store.compact(1000, &policy("1s:1m")).unwrap();
+    assert_eq!(store.query("a", 0).unwrap(), vec![(0, 2f64), (60, 10f64)]);
+
+    store.compact(1000, &policy("1s:1m, 2s:15m")).unwrap();
+    assert_eq!(store.query("a", 0).unwrap(), vec![(0, 4f64)]);
+    assert_eq!(store.read().unwrap()[0].count, 4);
+    std::fs::remove_file(store.path()).unwrap();
+  }
+
+  #[test]
+  fn compaction_drops_old_samples() {
+    let store = store("retention");
+
+    store.append(0, &[("a".to_string(), 1f64)]).unwrap();
+    store.append(100, &[("a".to_string(), 2f64)]).unwrap();
+    store.compact(100 + 7 * 24 * 60 * 60, &policy("")).unwrap();
+
+    assert_eq!(store.query("a", 0).unwrap(), vec![(100, 2f64)]);
+    std::fs::remove_file(store.path()).unwrap();
+  }
+
+  #[test]
+  fn ignores_truncated_records() {
+    let store = store("truncated");
+
+    store.append(1, &[("a".to_string(), 1f64), ("a".to_string(), 2f64)]).unwrap();
+
+    let bytes = std::fs::read(store.path()).unwrap();
+
+    std::fs::write(store.path(), &bytes[..bytes.len() - 3]).unwrap();
+    assert_eq!(store.query("a", 0).unwrap(), vec![(1, 1f64)]);
+    std::fs::remove_file(store.path()).unwrap();
+  }
+}
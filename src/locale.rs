@@ -0,0 +1,74 @@
+use std::ffi::CStr;
+
+/// Decimal and thousands separators of a locale
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NumericLocale {
+  pub decimal: char,
+  /// `None` when the locale doesn't group thousands, like `C`
+  pub thousands: Option<char>,
+}
+
+impl Default for NumericLocale {
+  fn default() -> Self {
+    Self {
+      decimal: '.',
+      thousands: None,
+    }
+  }
+}
+
+impl NumericLocale {
+  /// Separators of the locale in `LC_ALL`, `LC_NUMERIC` or `LANG`, in that order,
+  /// the process is switched back to the `C` locale afterwards so parsing numbers stays the same
+  pub fn from_env() -> Self {
+    fn first_char(ptr: *const libc::c_char) -> Option<char> {
+      if ptr.is_null() {
+        return None;
+      }
+
+      unsafe { CStr::from_ptr(ptr) }.to_str().ok()?.chars().next()
+    }
+
+    unsafe {
+      if libc::setlocale(libc::LC_NUMERIC, b"\0".as_ptr() as *const libc::c_char).is_null() {
+        return Self::default();
+      }
+
+      // only valid until the next setlocale, so it's copied first
+      let conv = libc::localeconv();
+      let locale = match conv.as_ref() {
+        Some(conv) => Self {
+          decimal: first_char(conv.decimal_point).unwrap_or('.'),
+          thousands: first_char(conv.thousands_sep),
+        },
+        None => Self::default(),
+      };
+
+      libc::setlocale(libc::LC_NUMERIC, b"C\0".as_ptr() as *const libc::c_char);
+      locale
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn current() -> String {
+    let name = unsafe { libc::setlocale(libc::LC_NUMERIC, std::ptr::null()) };
+
+    unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned()
+  }
+
+  #[test]
+  fn switches_back_to_c() {
+    // C.UTF-8 is there even on minimal systems, and has the same separators as C
+    std::env::set_var("LC_NUMERIC", "C.UTF-8");
+
+    let locale = NumericLocale::from_env();
+
+    assert_eq!(current(), "C");
+    assert_eq!(locale, NumericLocale { decimal: '.', thousands: None });
+    assert_eq!("1.5".parse::<f64>(), Ok(1.5));
+  }
+}
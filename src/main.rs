@@ -1,36 +1,124 @@
 #![feature(format_args_capture, associated_type_defaults, result_flattening)]
 
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use sysinfo::{ComponentExt, RefreshKind, ProcessorExt};
+use clap::Parser;
+use regex::Regex;
+use sysinfo::{ComponentExt, DiskExt, NetworksExt, ProcessExt, RefreshKind, ProcessorExt, SystemExt};
 
 use crate::bytes::{ByteFormat, ByteFormatConvert};
 use crate::color::{Color, ColorMode, set_color_mode};
 use crate::config::Config;
 use crate::fixed_system::{CPU, FixedSystem};
+use crate::graph::{Scale, Thresholds};
+use crate::history::{Aggregate, HistoryStore, RetentionPolicy, RollingHistory};
+use crate::locale::NumericLocale;
 use crate::log::LogMode;
-use crate::placeholders::PlaceholderExpander;
-use crate::util::TrimTrailingZerosToString;
+use crate::placeholders::{Macro, PlaceholderExpander, PlaceholderValue};
+use crate::temperature::TemperatureUnit;
+use crate::util::{format_duration, parse_duration, NumberFormat, Rounding, TrimTrailingZerosToString};
 
 mod bytes;
 mod color;
 mod config;
+mod diskstats;
+mod expr;
+mod filters;
 mod fixed_system;
+mod graph;
+mod history;
+mod locale;
 mod log;
+mod metrics;
+mod net;
+mod os;
 mod placeholders;
+mod temperature;
+mod top;
 mod util;
 
+const DEFAULT_CONFIG: &str = r#"
+symbol=${fg|gray}
+sep=${symbol}|
+used=${fg|green}${mem_used|.2}
+total=${symbol}/ ${fg|blue}${mem_total} ${symbol}GiB
+usage=${symbol}(${fg|yellow}${mem_usage|.2|with_suffix}${symbol}%)
+cpu=${fg|red}${cpu_usage|.2}${symbol}% ${fg|magenta}${cpu_temp|.2|with_suffix}
+output=${sep} ${cpu} ${sep} ${used} ${total} ${usage} ${sep} ${reset}
+"#;
+
+/// How long samples are kept in memory unless `history_memory` is set
+const DEFAULT_HISTORY_MEMORY: Duration = Duration::from_secs(10 * 60);
+
+/// Time between the two samples taken when printing once, the minimum sysinfo needs for cpu usage
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often the history store gets compacted while recording
+const COMPACT_INTERVAL: u64 = 60 * 60;
+
+/// Most of a file `${file|...}` reads, so a wrong path like a log doesn't end up in the output whole
+const FILE_LIMIT: u64 = 4096;
+
+#[derive(Parser)]
+#[clap(version, about = "shows your system usage")]
+struct Opts {
+  /// Config file to use instead of the built-in one
+  #[clap(short, long)]
+  config: Option<PathBuf>,
+  #[clap(subcommand)]
+  command: Option<Command>,
+}
+
+#[derive(Parser)]
+enum Command {
+  /// Prints the output every interval
+  Watch {
+    /// Time between refreshes, e.g. `500ms`, `1s` or `1m`
+    #[clap(short, long, default_value = "1s", parse(try_from_str = parse_duration))]
+    interval: Duration,
+    /// Records every metric into the history store
+    #[clap(short, long)]
+    record: bool,
+  },
+  /// Full-screen dashboard, tab switches panels, arrows scroll and q quits
+  Top {
+    /// Time between refreshes, e.g. `500ms`, `1s` or `1m`
+    #[clap(short, long, default_value = "1s", parse(try_from_str = parse_duration))]
+    interval: Duration,
+  },
+  /// Lists every temperature sensor, their labels can be used with `${temp|label=...}`
+  Sensors,
+  /// Queries aggregates of a metric from the history store
+  History {
+    /// Metric to query, e.g. `mem_usage` or `cpu_temp`
+    #[clap(short, long)]
+    metric: String,
+    /// How far back to look, e.g. `15m`, `1h` or `7d`
+    #[clap(short, long, default_value = "1h", parse(try_from_str = parse_duration))]
+    since: Duration,
+    /// Comma separated aggregates out of `min`, `max`, `avg` and `delta`
+    #[clap(short, long, default_value = "avg,max")]
+    agg: String,
+  },
+}
+
 macro_rules! placeholder {
   ($($vis:vis $name:ident ($slf:ident, $args:ident) $b:block)+) => {
     $(
-      $vis fn $name(&$slf, $args: Args) -> String $b
+      $vis fn $name(&$slf, $args: Args) -> impl PlaceholderValue $b
     )+
 
     #[allow(unused)]
     fn _get_placeholder(&self, name: &str) -> Option<PlaceholderFn> {
       match name {
-        $(stringify!($name) => Some(Box::new(Self::$name)),)+
+        $(stringify!($name) => Some(Box::new(|data: &Self, args| Self::$name(data, args).into_value())),)+
         _ => None
       }
     }
@@ -39,63 +127,239 @@ macro_rules! placeholder {
 
 struct Data {
   sys: FixedSystem,
+  config: Config,
   custom: HashMap<String, String>,
+  /// Sections with parameters, expanded whenever they are used
+  macros: HashMap<String, Macro>,
+  /// Macros being expanded right now, to catch ones that use themselves
+  expanding: RefCell<Vec<String>>,
+  /// Items of the `each` placeholders being expanded right now, the innermost last
+  items: RefCell<Vec<Item>>,
+  history: HistoryStore,
+  recent: RollingHistory,
+  /// Separators from the locale and settings, placeholders start out with
+  number: NumberFormat,
+  /// Shown for missing values, from the `missing` setting
+  missing: String,
 }
 
 impl Data {
-  fn new(sys: FixedSystem, sections: Vec<(String, String)>) -> Self {
+  fn new(sys: FixedSystem, config: Config) -> Self {
+    let history = config
+      .setting::<PathBuf>("history_path")
+      .unwrap_or_else(HistoryStore::default_path);
+    let memory = config
+      .setting::<String>("history_memory")
+      .and_then(|it| parse_duration(&it).map_err(|err| log::warn!("{err}")).ok())
+      .unwrap_or(DEFAULT_HISTORY_MEMORY);
+
+    let number = number_format(&config);
+    let missing = config.setting("missing").unwrap_or_default();
     let mut _self = Self {
       sys,
-      custom: HashMap::with_capacity(sections.len() * 2),
+      number,
+      missing,
+      macros: HashMap::new(),
+      expanding: RefCell::new(vec![]),
+      items: RefCell::new(vec![]),
+      custom: HashMap::with_capacity(config.sections.len() * 2),
+      config,
+      history: HistoryStore::open(history),
+      recent: RollingHistory::new(memory),
     };
 
-    for (name, value) in sections {
-      _self.custom.insert(name, _self.expand_placeholders(&value));
+    _self.load_macros();
+    _self.record_recent();
+    _self.expand_sections();
+    _self
+  }
+
+  /// Sections with parameters, invalid ones and ones named like a built-in placeholder are logged and skipped
+  fn load_macros(&mut self) {
+    let mut macros = HashMap::new();
+
+    for (key, body) in &self.config.sections {
+      match Macro::parse(key, body) {
+        Some(Ok(it)) if self._get_placeholder(&it.name).is_some() || macros.contains_key(&it.name) => {
+          log::warn!("Macro '{}' is already defined", it.name)
+        }
+        Some(Ok(it)) => {
+          macros.insert(it.name.clone(), it);
+        }
+        Some(Err(err)) => log::warn!("{err}"),
+        None => {}
+      }
+    }
+
+    self.macros = macros;
+  }
+
+  /// Sections are expanded in order, so they can only use sections defined before them,
+  /// the ones ending in `?` are empty when any of their placeholders is missing
+  fn expand_sections(&mut self) {
+    let sections = std::mem::take(&mut self.config.sections);
+
+    self.custom.clear();
+
+    for (name, value) in &sections {
+      if Macro::parse(name, value).is_some() {
+        continue;
+      }
+
+      let (name, value) = match name.strip_suffix('?') {
+        Some(name) => (name, self.expand_placeholders_if_complete(value).unwrap_or_default()),
+        None => (name.as_str(), self.expand_placeholders(value)),
+      };
+
+      self.custom.insert(name.to_string(), value);
     }
 
-    _self
+    self.config.sections = sections;
+  }
+
+  fn refresh(&mut self) {
+    self.sys.refresh();
+    self.record_recent();
+    self.expand_sections();
+  }
+
+  fn record_recent(&mut self) {
+    let now = Instant::now();
+
+    for (key, value) in metrics::sample_all(&self.sys) {
+      self.recent.push(&key, now, value);
+    }
   }
 
-  fn expand_placeholders_from(sys: FixedSystem, config: Config) -> String {
-    let mut _self = Self::new(sys, config.sections);
+  fn output(&self) -> String {
+    self.expand_placeholders(&self.config.output)
+  }
+
+  fn retention_policy(&self) -> RetentionPolicy {
+    let mut policy = RetentionPolicy::default();
+
+    if let Some(retention) = self.config.setting::<String>("history_retention") {
+      match parse_duration(&retention) {
+        Ok(retention) => policy.retention = retention,
+        Err(err) => log::warn!("{err}"),
+      }
+    }
+
+    if let Some(downsample) = self.config.setting::<String>("history_downsample") {
+      match RetentionPolicy::parse_downsample(&downsample) {
+        Ok(downsample) => policy.downsample = downsample,
+        Err(err) => log::warn!("{err}"),
+      }
+    }
 
-    _self.expand_placeholders(&config.output)
+    policy
+  }
+
+  fn record_history(&self, time: u64) -> std::io::Result<()> {
+    self.history.append(time, &metrics::sample_all(&self.sys))
   }
 }
 
 #[derive(Debug)]
 struct Args {
   name: String,
-  precision: usize,
+  /// Arguments as they were given, for macros
+  raw: Vec<String>,
+  number: NumberFormat,
   format: ByteFormat,
+  temperature: TemperatureUnit,
   with_suffix: bool,
   fg: String,
   bg: String,
+  /// `min=`, `max=`, `avg=` or `delta=` over the given time window,
+  /// `delta` without a window is the change since the last refresh
+  aggregate: Option<(Aggregate, Option<Duration>)>,
+  /// Metric a graph is drawn for
+  metric: Option<String>,
+  /// `core=N` or `core=all`
+  core: Option<String>,
+  /// Exact label of a sensor
+  label: Option<String>,
+  /// `match=` regex of a sensor label
+  sensor: Option<Regex>,
+  /// Mount point of a disk
+  mount: Option<String>,
+  /// Block device like `nvme0n1`
+  dev: Option<String>,
+  /// Network interface like `eth0`, `auto` for the one with the default route
+  iface: Option<String>,
+  /// Smallest and largest unit `auto` and `auto_si` may pick
+  min_unit: Option<ByteFormat>,
+  max_unit: Option<ByteFormat>,
+  /// Pads `auto` and `auto_si` to a fixed width
+  fixed_width: bool,
+  /// Shows network rates in bits instead of bytes
+  bits: bool,
+  /// Shows IPv6 instead of IPv4 addresses
+  ipv6: bool,
+  /// Separator when a placeholder prints a value for every core or sensor
+  sep: String,
+  /// Prints every core as a block character instead of a number
+  blocks: bool,
+  width: usize,
+  fill: String,
+  empty: String,
+  /// `range=lo:hi` of graphs, defaults to the range of the metric
+  scale: Option<Scale>,
+  thresholds: Thresholds,
 }
 
 impl Default for Args {
   fn default() -> Self {
     Self {
       name: String::new(),
-      precision: 2,
+      raw: vec![],
+      number: NumberFormat::default(),
       format: ByteFormat::GiB,
+      temperature: TemperatureUnit::Celsius,
       with_suffix: false,
       fg: String::new(),
       bg: String::new(),
+      aggregate: None,
+      metric: None,
+      core: None,
+      label: None,
+      sensor: None,
+      mount: None,
+      dev: None,
+      iface: None,
+      min_unit: None,
+      max_unit: None,
+      fixed_width: false,
+      bits: false,
+      ipv6: false,
+      sep: " ".to_string(),
+      blocks: false,
+      width: 10,
+      fill: "█".to_string(),
+      empty: "░".to_string(),
+      scale: None,
+      thresholds: Thresholds::default(),
     }
   }
 }
 
 impl Args {
-  fn from(name: &str, args: &[&str]) -> Self {
-    let mut out = Self::default();
+  fn from(name: &str, args: &[&str], number: NumberFormat) -> Self {
+    let mut out = Self {
+      number,
+      ..Self::default()
+    };
+    // these can be sizes like `512MiB`, which need the metric they are for to be known
+    let mut limits = vec![];
 
     out.name = name.to_string();
+    out.raw = args.iter().map(|it| it.to_string()).collect();
 
     for arg in args {
       if arg.starts_with('.') {
         match usize::from_str(&arg[1..]) {
-          Ok(value) => out.precision = value,
+          Ok(value) => out.number.precision = value,
           Err(err) => log::warn!("{:?}", err)
         }
       }
@@ -114,22 +378,209 @@ impl Args {
         };
       }
 
+      if metrics::METRICS.contains(arg) {
+        out.metric = Some(arg.to_string());
+      }
+
+      if let Some((key, value)) = arg.split_once('=') {
+        match key {
+          "width" => match usize::from_str(value) {
+            Ok(value) => out.width = value,
+            Err(err) => log::warn!("{err:?}")
+          },
+          "core" => out.core = Some(value.to_string()),
+          "label" => out.label = Some(value.to_string()),
+          "mount" => out.mount = Some(value.to_string()),
+          "dev" => out.dev = Some(value.to_string()),
+          "round" => match Rounding::from_str(value) {
+            Ok(rounding) => out.number.rounding = rounding,
+            Err(err) => log::warn!("{err}")
+          },
+          "min_width" => match usize::from_str(value) {
+            Ok(value) => out.number.width = value,
+            Err(err) => log::warn!("{err:?}")
+          },
+          "min_unit" | "max_unit" => match ByteFormat::from_str(value) {
+            Ok(format) if key == "min_unit" => out.min_unit = Some(format),
+            Ok(format) => out.max_unit = Some(format),
+            Err(err) => log::warn!("{err}")
+          },
+          "iface" => out.iface = Some(value.to_string()),
+          "match" => match Regex::new(value) {
+            Ok(regex) => out.sensor = Some(regex),
+            Err(err) => log::warn!("{err}")
+          },
+          "sep" => out.sep = value.to_string(),
+          "fill" => out.fill = value.to_string(),
+          "empty" => out.empty = value.to_string(),
+          "range" | "warn" | "crit" => limits.push((key, value)),
+          key => if let Ok(aggregate) = Aggregate::from_str(key) {
+            match parse_duration(value) {
+              Ok(window) => out.aggregate = Some((aggregate, Some(window))),
+              Err(err) => log::warn!("{err}")
+            }
+          }
+        }
+      }
+
       match *arg {
         "with_suffix" => out.with_suffix = true,
-        "kb" => out.format = ByteFormat::KB,
-        "kib" => out.format = ByteFormat::KiB,
-        "mb" => out.format = ByteFormat::MB,
-        "mib" => out.format = ByteFormat::MiB,
-        "gb" => out.format = ByteFormat::GB,
-        "gib" => out.format = ByteFormat::GiB,
-        "tb" => out.format = ByteFormat::GB,
-        "tib" => out.format = ByteFormat::GiB,
+        "blocks" => out.blocks = true,
+        "bits" => out.bits = true,
+        "bytes" => out.bits = false,
+        "v4" => out.ipv6 = false,
+        "v6" => out.ipv6 = true,
+        "delta" => out.aggregate = Some((Aggregate::Delta, None)),
+        "fixed" => out.fixed_width = true,
+        "keep_zeros" => out.number.keep_zeros = true,
+        "zero_pad" => out.number.zero_pad = true,
+        "sign" => out.number.sign = true,
+        "group" => out.number.grouping = true,
+        unit => if let Ok(format) = ByteFormat::from_str(unit) {
+          out.format = format;
+        } else if let Ok(unit) = TemperatureUnit::from_str(unit) {
+          out.temperature = unit;
+        }
+      }
+    }
+
+    let unit = metrics::byte_unit(out.metric.as_deref().unwrap_or(name));
+
+    for (key, value) in limits {
+      let result = match key {
+        "range" => match value.split_once(':') {
+          Some((lo, hi)) => Self::limit(lo, unit)
+            .and_then(|lo| Ok(Scale::new(lo, Self::limit(hi, unit)?)))
+            .map(|scale| out.scale = Some(scale)),
+          None => Err(format!("Invalid range '{value}', expected lo:hi")),
+        },
+        "warn" => Self::limit(value, unit).map(|it| out.thresholds.warn = Some(it)),
+        _ => Self::limit(value, unit).map(|it| out.thresholds.crit = Some(it)),
+      };
+
+      if let Err(err) = result {
+        log::warn!("{err}");
+      }
+    }
+
+    out
+  }
+
+  /// A plain number, or for metrics that are sizes a size like `1.5GiB` in the metric's unit
+  fn limit(value: &str, unit: Option<ByteFormat>) -> Result<f64, String> {
+    match (f64::from_str(value), unit) {
+      (Ok(value), _) => Ok(value),
+      (Err(_), Some(unit)) => Ok(unit.convert_from_bytes(bytes::parse_bytes(value)?)),
+      (Err(err), None) => Err(format!("Invalid number '{value}': {err}")),
+    }
+  }
+}
+
+/// One disk, interface, sensor, core or process that `each` renders its template for
+#[derive(Debug, Clone, Default)]
+struct Item {
+  /// What `filter=` matches
+  name: String,
+  /// Placeholders only there inside `each` like `${mount}`, with a number to sort them by if they are one
+  fields: Vec<(&'static str, String, Option<f64>)>,
+  /// Arguments placeholders inside `each` get in front of their own, like `mount=/`
+  args: Vec<String>,
+}
+
+impl Item {
+  fn field(&self, name: &str) -> Option<&str> {
+    self.fields
+      .iter()
+      .find(|(field, ..)| *field == name)
+      .map(|(_, value, _)| value.as_str())
+  }
+}
+
+/// Options of `each`, applied in the order filter, sort and limit no matter the order they are given in
+#[derive(Debug, Default)]
+struct EachOptions {
+  sep: String,
+  /// Matches the name of items to keep
+  filter: Option<Regex>,
+  /// Field or metric to sort by, descending with a leading `-`
+  sort: Option<String>,
+  limit: Option<usize>,
+}
+
+impl EachOptions {
+  fn parse(options: impl IntoIterator<Item=String>) -> Self {
+    let mut out = Self::default();
+
+    for option in options {
+      match option.split_once('=') {
+        Some(("sep", value)) => out.sep = value.to_string(),
+        Some(("filter", value)) => match Regex::new(value) {
+          Ok(regex) => out.filter = Some(regex),
+          Err(err) => log::warn!("{err}")
+        },
+        Some(("sort", value)) => out.sort = Some(value.to_string()),
+        Some(("limit", value)) => match usize::from_str(value) {
+          Ok(limit) => out.limit = Some(limit),
+          Err(err) => log::warn!("{:?}", err)
+        },
         _ => {}
       }
     }
 
     out
   }
+
+  /// The items to render, `metric` is what items get sorted by when the sort isn't one of their fields
+  fn apply(&self, mut items: Vec<Item>, metric: impl Fn(&str, &Item) -> Option<f64>) -> Vec<Item> {
+    if let Some(filter) = &self.filter {
+      items.retain(|it| filter.is_match(&it.name));
+    }
+
+    if let Some(sort) = &self.sort {
+      sort_items(&mut items, sort, metric);
+    }
+
+    if let Some(limit) = self.limit {
+      items.truncate(limit);
+    }
+
+    items
+  }
+}
+
+/// Sorts by a field or a metric of the items like `disk_usage`, descending with a leading `-`,
+/// numbers sort by value and go in front of missing ones
+fn sort_items(items: &mut Vec<Item>, by: &str, metric: impl Fn(&str, &Item) -> Option<f64>) {
+  let (by, descending) = match by.strip_prefix('-') {
+    Some(by) => (by, true),
+    None => (by, false),
+  };
+  let key = |item: &Item| -> (Option<f64>, String) {
+    match item.fields.iter().find(|(field, ..)| *field == by) {
+      Some((_, value, number)) => (*number, value.clone()),
+      None if by == "name" => (None, item.name.clone()),
+      None => (metric(by, item), String::new()),
+    }
+  };
+
+  if by != "name" && !metrics::METRICS.contains(&by) && items.iter().all(|it| it.field(by).is_none()) {
+    log::warn!("Can't sort by '{by}', it is neither a field of the items nor a metric");
+    return;
+  }
+
+  let mut keyed = items.drain(..).map(|it| (key(&it), it)).collect::<Vec<_>>();
+
+  keyed.sort_by(|((a, a_text), _), ((b, b_text), _)| {
+    let order = match (a, b) {
+      (Some(a), Some(b)) => a.total_cmp(b),
+      (Some(_), None) => return Ordering::Less,
+      (None, Some(_)) => return Ordering::Greater,
+      (None, None) => a_text.cmp(b_text),
+    };
+
+    if descending { order.reverse() } else { order }
+  });
+  items.extend(keyed.into_iter().map(|(_, it)| it));
 }
 
 type PlaceholderFn = placeholders::PlaceholderFn<Data, Args>;
@@ -142,37 +593,22 @@ impl Data {
     bg(self, args) { args.bg }
 
     //region MEM
-    mem_usage(self, args) {
-      let percent = (self.sys.used_memory() as f64 / self.sys.total_memory() as f64) * 100f64;
-
-      percent.trim_trailing_zeros_with_precision(args.precision)
-    }
-
-    mem_used(self, args) { self.mem_placeholder(FixedSystem::used_memory, args) }
-    mem_free(self, args) { self.mem_placeholder(FixedSystem::free_memory, args) }
-    mem_available(self, args) { self.mem_placeholder(FixedSystem::available_memory, args) }
-    mem_total(self, args) { self.mem_placeholder(FixedSystem::total_memory, args) }
-    swap_used(self, args) { self.mem_placeholder(FixedSystem::used_swap, args) }
-    swap_total(self, args) { self.mem_placeholder(FixedSystem::total_swap, args) }
-    swap_free(self, args) { self.mem_placeholder(FixedSystem::free_swap, args) }
+    mem_usage(self, args) { self.number_placeholder("mem_usage", args) }
+    mem_used(self, args) { self.mem_placeholder("mem_used", args) }
+    mem_free(self, args) { self.mem_placeholder("mem_free", args) }
+    mem_available(self, args) { self.mem_placeholder("mem_available", args) }
+    mem_total(self, args) { self.mem_placeholder("mem_total", args) }
+    swap_used(self, args) { self.mem_placeholder("swap_used", args) }
+    swap_total(self, args) { self.mem_placeholder("swap_total", args) }
+    swap_free(self, args) { self.mem_placeholder("swap_free", args) }
+    swap_usage(self, args) { self.number_placeholder("swap_usage", args) }
     //endregion
 
     //region CPU
-    cpu_usage(self, args) {
-      self.cpu_placeholder(&args, |cpu, args| cpu
-        .processor
-        .cpu_usage()
-        .trim_trailing_zeros_with_precision(args.precision)
-      )
-    }
-
-    cpu_freq(self, args) {
-      self.cpu_placeholder(&args, |cpu, _| cpu
-        .processor
-        .frequency()
-        .to_string()
-      )
-    }
+    cpu_usage(self, args) { self.core_placeholder("cpu_usage", args) }
+    cpu_freq(self, args) { self.core_placeholder("cpu_freq", args) }
+    cpu_cores(self, _args) { self.sys.physical_core_count().map(|it| it.to_string()) }
+    cpu_threads(self, _args) { self.sys.processors().len().to_string() }
 
     cpu_name(self, args) {
       self.cpu_placeholder(&args, |cpu, _| cpu
@@ -190,54 +626,438 @@ impl Data {
       )
     }
 
-    cpu_temp(self, args) {
-      self.cpu_placeholder(&args, |cpu, args| cpu
-        .component
-        .temperature()
-        .trim_trailing_zeros_with_precision(args.precision)
-      )
+    cpu_temp(self, args) { self.temp_placeholder("cpu_temp", args) }
+    cpu_critical_temp(self, args) { self.temp_placeholder("cpu_critical_temp", args) }
+    cpu_max_temp(self, args) { self.temp_placeholder("cpu_max_temp", args) }
+    //endregion
+
+    //region TEMP
+    temp(self, args) { self.temp_placeholder("temp", args) }
+    temp_max(self, args) { self.temp_placeholder("temp_max", args) }
+    temp_critical(self, args) { self.temp_placeholder("temp_critical", args) }
+
+    sensors(self, args) {
+      self.sys.components()
+        .iter()
+        .map(|it| it.label())
+        .collect::<Vec<_>>()
+        .join(&args.sep)
     }
+    //endregion
 
-    cpu_critical_temp(self, args) {
-      self.cpu_placeholder(&args, |cpu, args| cpu
-        .component
-        .critical()
-        .unwrap_or(f32::NAN)
-        .trim_trailing_zeros_with_precision(args.precision)
-      )
+    //region DISK
+    disk_used(self, args) { self.bytes_placeholder("disk_used", ByteFormat::Bytes, args) }
+    disk_free(self, args) { self.bytes_placeholder("disk_free", ByteFormat::Bytes, args) }
+    disk_total(self, args) { self.bytes_placeholder("disk_total", ByteFormat::Bytes, args) }
+    disk_usage(self, args) { self.number_placeholder("disk_usage", args) }
+    disk_read(self, args) { self.rate_placeholder("disk_read", args) }
+    disk_write(self, args) { self.rate_placeholder("disk_write", args) }
+    disk_read_iops(self, args) { self.number_placeholder("disk_read_iops", args) }
+    disk_write_iops(self, args) { self.number_placeholder("disk_write_iops", args) }
+    disk_util(self, args) { self.number_placeholder("disk_util", args) }
+
+    disk_fs(self, args) {
+      self.disk_placeholder(&args, |disk| String::from_utf8_lossy(disk.file_system()).into_owned())
     }
 
-    cpu_max_temp(self, args) {
-      self.cpu_placeholder(&args, |cpu, args| cpu
-        .component
-        .max()
-        .trim_trailing_zeros_with_precision(args.precision)
-      )
+    disk_name(self, args) {
+      self.disk_placeholder(&args, |disk| disk.name().to_string_lossy().into_owned())
+    }
+
+    disk_removable(self, args) {
+      self.disk_placeholder(&args, |disk| disk.is_removable().to_string())
     }
     //endregion
 
-    //region DISK
+    //region NET
+    net_rx(self, args) { self.rate_placeholder("net_rx", args) }
+    net_tx(self, args) { self.rate_placeholder("net_tx", args) }
+    net_rx_total(self, args) { self.bytes_placeholder("net_rx_total", ByteFormat::Bytes, args) }
+    net_tx_total(self, args) { self.bytes_placeholder("net_tx_total", ByteFormat::Bytes, args) }
+    net_rx_packets(self, args) { self.number_placeholder("net_rx_packets", args) }
+    net_tx_packets(self, args) { self.number_placeholder("net_tx_packets", args) }
+    net_rx_errors(self, args) { self.number_placeholder("net_rx_errors", args) }
+    net_tx_errors(self, args) { self.number_placeholder("net_tx_errors", args) }
+
+    net_ip(self, args) {
+      self.net_placeholder(&args, |iface, args| net::address(iface, args.ipv6).map(|it| it.to_string()))
+    }
+
+    net_ip6(self, args) {
+      self.net_placeholder(&args, |iface, _| net::address(iface, true).map(|it| it.to_string()))
+    }
+
+    net_mac(self, args) { self.net_placeholder(&args, |iface, _| net::mac(iface)) }
+    net_mtu(self, args) { self.net_placeholder(&args, |iface, _| net::mtu(iface).map(|it| it.to_string())) }
+
+    net_state(self, args) {
+      self.net_placeholder(&args, |iface, _| net::is_up(iface).map(|it| if it { "up" } else { "down" }.to_string()))
+    }
+
+    net_gateway(self, args) {
+      let iface = args.iface.as_deref().filter(|it| *it != "auto");
+
+      net::default_route(iface).map(|it| it.gateway.to_string())
+    }
     //endregion
+
+    //region SYSTEM
+    env(self, args) {
+      std::env::var(args.raw.first()?).ok()
+    }
+
+    file(self, args) {
+      let content = os::read_file(Path::new(args.raw.first()?), FILE_LIMIT)?;
+
+      Some(if args.raw.iter().any(|it| it == "trim") { content.trim().to_string() } else { content })
+    }
+
+    time(self, args) { os::format_time(args.raw.first().map_or("%H:%M", String::as_str)) }
+    date(self, args) { os::format_time(args.raw.first().map_or("%Y-%m-%d", String::as_str)) }
+
+    hostname(self, _args) { os::hostname() }
+    user(self, _args) { os::user() }
+
+    uptime(self, args) {
+      let uptime = self.sys.uptime();
+
+      if args.raw.iter().any(|it| it == "human") {
+        format_duration(Duration::from_secs(uptime))
+      } else {
+        uptime.to_string()
+      }
+    }
+    //endregion
+
+    //region GRAPH
+    spark(self, args) {
+      self.graph_placeholder(&args, |values, scale| graph::sparkline(values, scale, args.width, args.thresholds))
+    }
+
+    braille(self, args) {
+      self.graph_placeholder(&args, |values, scale| graph::braille(values, scale, args.width, args.thresholds))
+    }
+
+    bar(self, args) {
+      let metric = args.metric.as_ref()?;
+      let value = self.metric(metric, &args)?;
+      let selector = self.selector(metric, &args).flatten();
+      let scale = args.scale
+        .or_else(|| metrics::scale(&self.sys, metric, selector.as_deref()))
+        .unwrap_or(Scale::new(0f64, 100f64));
+
+      Some(graph::bar(value, scale, args.width, &args.fill, &args.empty, args.thresholds))
+    }
+    //endregion
+
+    //region EACH
+    each(self, args) {
+      let (collection, template) = match args.raw.as_slice() {
+        [collection, template, ..] => (collection, template),
+        _ => {
+          log::warn!("each takes a collection and a template, like ${{each|disks|${{mount}}}}");
+          return None;
+        }
+      };
+      let options = EachOptions::parse(args.raw[2..].iter().map(|it| self.expand_placeholders(it)));
+      let items = options.apply(self.collection(collection)?, |name, item| {
+        self.metric(name, &Args::from(name, &item.args.iter().map(String::as_str).collect::<Vec<_>>(), self.number))
+      });
+
+      Some(items
+        .into_iter()
+        .map(|item| {
+          self.items.borrow_mut().push(item);
+          let out = self.expand_placeholders(template);
+          self.items.borrow_mut().pop();
+          out
+        })
+        .collect::<Vec<_>>()
+        .join(&options.sep))
+    }
+    //endregion
+  }
+
+  /// Draws the recent values of the metric in `args`, scaled by the metric's range if there is one
+  fn graph_placeholder(&self, args: &Args, draw: impl Fn(&[f64], Scale) -> String) -> Option<String> {
+    let metric = args.metric.as_ref()?;
+    let selector = self.selector(metric, args)?;
+    let values = self.recent.last(&metrics::key(metric, selector.as_deref()), args.width * 2);
+    let scale = args.scale
+      .or_else(|| metrics::scale(&self.sys, metric, selector.as_deref()))
+      .unwrap_or_else(|| Scale::fit(&values));
+
+    Some(draw(&values, scale))
+  }
+
+  /// Items of a collection for `each`, `None` for unknown collections
+  fn collection(&self, name: &str) -> Option<Vec<Item>> {
+    let mut items: Vec<Item> = match name {
+      "disks" => self.sys.disks()
+        .iter()
+        .map(|disk| {
+          let mount = disk.mount_point().to_string_lossy().into_owned();
+          let dev = disk.name().to_string_lossy().trim_start_matches("/dev/").to_string();
+          let fs = String::from_utf8_lossy(disk.file_system()).into_owned();
+
+          Item {
+            name: mount.clone(),
+            args: vec![format!("mount={mount}"), format!("dev={dev}")],
+            fields: vec![("mount", mount, None), ("dev", dev, None), ("fs", fs, None)],
+          }
+        })
+        .collect(),
+      "networks" | "ifaces" => self.sys.networks()
+        .iter()
+        .map(|(iface, _)| Item {
+          name: iface.clone(),
+          args: vec![format!("iface={iface}")],
+          fields: vec![("iface", iface.clone(), None)],
+        })
+        .collect(),
+      "sensors" => self.sys.components()
+        .iter()
+        .map(|it| Item {
+          name: it.label().to_string(),
+          args: vec![format!("label={}", it.label())],
+          fields: vec![("label", it.label().to_string(), None)],
+        })
+        .collect(),
+      "cores" => (0..self.sys.processors().len())
+        .map(|core| Item {
+          name: core.to_string(),
+          args: vec![format!("core={core}")],
+          fields: vec![("core", core.to_string(), Some(core as f64))],
+        })
+        .collect(),
+      "processes" => self.sys.processes()
+        .values()
+        .map(|process| {
+          let cpu = process.cpu_usage() as f64;
+          let mem = process.memory().convert_to_display(ByteFormat::KiB, ByteFormat::Auto).with_number_format(self.number);
+
+          Item {
+            name: process.name().to_string(),
+            args: vec![],
+            fields: vec![
+              ("pid", process.pid().to_string(), Some(process.pid() as f64)),
+              ("process", process.name().to_string(), None),
+              ("process_cpu", self.number.format(cpu), Some(cpu)),
+              ("process_mem", mem.to_string(), Some(process.memory() as f64)),
+            ],
+          }
+        })
+        .collect(),
+      _ => {
+        log::warn!("Unknown collection '{name}', expected disks, networks, sensors, cores or processes");
+        return None;
+      }
+    };
+
+    // interfaces and processes come out of hash maps
+    if matches!(name, "networks" | "ifaces" | "processes") {
+      items.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    Some(items)
+  }
+
+  /// Interface in `iface=`, or the one with the default route without one
+  fn net_placeholder(&self, args: &Args, f: fn(&str, &Args) -> Option<String>) -> Option<String> {
+    let iface = match args.iface.as_deref() {
+      Some(iface) if iface != "auto" => Some(iface.to_string()),
+      _ => net::default_route(None).map(|it| it.iface),
+    };
+
+    f(&iface?, args)
   }
 
   fn cpu_placeholder(&self, args: &Args, f: fn(&CPU, &Args) -> String) -> String {
-    let cpu = self.sys.cpu();
+    f(&self.sys.cpu(), args)
+  }
+
+  /// Current value of a metric, or its aggregate if one was asked for
+  fn metric(&self, name: &str, args: &Args) -> Option<f64> {
+    self.selected_metric(name, self.selector(name, args)?.as_deref(), args.aggregate)
+  }
+
+  /// What a metric is selected by, like the core or the sensor label,
+  /// `None` when `match=` doesn't match any sensor or there is no default route for `iface=auto`
+  fn selector(&self, name: &str, args: &Args) -> Option<Option<String>> {
+    if let Some(sensor) = &args.sensor {
+      return self.sys.components()
+        .iter()
+        .map(|it| it.label())
+        .find(|it| sensor.is_match(it))
+        .map(|it| Some(it.to_string()));
+    }
+
+    match name {
+      "disk_read" | "disk_write" | "disk_read_iops" | "disk_write_iops" | "disk_util" => Some(args.dev.clone()),
+      name if name.starts_with("disk_") => Some(args.mount.clone()),
+      name if name.starts_with("net_") && args.iface.as_deref() == Some("auto") => {
+        net::default_route(None).map(|it| Some(it.iface))
+      }
+      name if name.starts_with("net_") => Some(args.iface.clone()),
+      name if name.starts_with("cpu_") => Some(args.core.clone()),
+      _ => Some(args.label.clone()),
+    }
+  }
+
+  fn selected_metric(&self, name: &str, selector: Option<&str>, aggregate: Option<(Aggregate, Option<Duration>)>) -> Option<f64> {
+    match aggregate {
+      Some((aggregate, window)) => self.aggregate(&metrics::key(name, selector), aggregate, window),
+      None => metrics::sample(&self.sys, name, selector),
+    }
+  }
+
+  /// Aggregates from memory, unless the window goes further back than memory does
+  /// and the history store has something for it
+  fn aggregate(&self, name: &str, aggregate: Aggregate, window: Option<Duration>) -> Option<f64> {
+    let recent = self.recent.values(name, window);
+
+    if let Some(window) = window.filter(|window| !self.recent.covers(name, *window)) {
+      let since = history::now().saturating_sub(window.as_secs());
 
-    match cpu {
-      Some(cpu) => f(&cpu, args),
-      None => "".to_string(),
+      match self.history.query(name, since) {
+        Ok(samples) if !samples.is_empty() => {
+          return aggregate.apply(samples.into_iter().map(|(_, value)| value));
+        }
+        Ok(_) => {}
+        Err(err) => log::warn!("Could not read history from {:?}: {err}", self.history.path()),
+      }
     }
+
+    aggregate.apply(recent)
   }
 
-  fn mem_placeholder(&self, val: fn(&FixedSystem) -> u64, args: Args) -> String {
-    let val = val(&self.sys).convert_to_display(ByteFormat::KiB, args.format);
+  /// A metric for a single core with `core=N`, or every core with `core=all`
+  fn core_placeholder(&self, name: &str, args: Args) -> Option<String> {
+    if args.core.as_deref() != Some("all") {
+      return self.number_placeholder(name, args);
+    }
+
+    let values = metrics::selectors(&self.sys, name)
+      .into_iter()
+      .map(|core| self.selected_metric(name, Some(&core), args.aggregate).unwrap_or_default())
+      .collect::<Vec<_>>();
+
+    if args.blocks {
+      let scale = args.scale
+        .or_else(|| metrics::scale(&self.sys, name, None))
+        .unwrap_or_else(|| Scale::fit(&values));
+
+      return Some(graph::sparkline(&values, scale, values.len(), args.thresholds));
+    }
+
+    Some(values
+      .iter()
+      .map(|it| args.number.format(*it))
+      .collect::<Vec<_>>()
+      .join(&args.sep))
+  }
+
+  /// Expands a macro with the arguments of its placeholder, a macro that ends up using itself is missing
+  fn macro_placeholder(&self, name: &str, args: &[String]) -> Option<String> {
+    if self.expanding.borrow().iter().any(|it| it == name) {
+      log::warn!("Macro '{name}' uses itself through {} -> {name}", self.expanding.borrow().join(" -> "));
+      return None;
+    }
+
+    let body = self.macros.get(name)?.substitute(args, self.placeholder_prefix(), self.placeholder_suffix());
+
+    self.expanding.borrow_mut().push(name.to_string());
+    let out = self.expand_placeholders(&body);
+    self.expanding.borrow_mut().pop();
+
+    Some(out)
+  }
+
+  /// Arithmetic over metrics like `${= mem_used / mem_total * 100}`, metrics that are sizes are in bytes
+  fn expr_placeholder(&self, expr: &str, args: Args) -> Option<String> {
+    let value = expr::eval(expr, &|name, selector| {
+      if !metrics::METRICS.contains(&name) {
+        log::warn!("Unknown metric '{name}' in expression '{expr}'");
+        return None;
+      }
+
+      // in bytes, so they mix with sizes like `10GiB`
+      let value = self.selected_metric(name, selector, None)?;
+
+      Some(metrics::byte_unit(name).map_or(value, |unit| value * unit.convert_to_bytes(1) as f64))
+    });
+
+    match value {
+      Ok(value) if value.is_finite() => Some(args.number.format(value)),
+      Ok(_) => None,
+      Err(err) => {
+        log::warn!("{err}");
+        None
+      }
+    }
+  }
+
+  fn number_placeholder(&self, name: &str, args: Args) -> Option<String> {
+    self.metric(name, &args).map(|it| args.number.format(it))
+  }
+
+  fn temp_placeholder(&self, name: &str, args: Args) -> Option<String> {
+    let celsius = self.metric(name, &args)?;
+    let val = match args.aggregate {
+      Some((Aggregate::Delta, _)) => args.temperature.convert_difference_from_celsius(celsius),
+      _ => args.temperature.convert_from_celsius(celsius),
+    };
+    let val = args.number.format(val);
 
     if args.with_suffix {
-      val.to_string()
+      Some(format!("{val}{}", args.temperature))
     } else {
-      val.to_string_no_suffix()
+      Some(val)
     }
   }
+
+  fn disk_placeholder(&self, args: &Args, f: fn(&sysinfo::Disk) -> String) -> Option<String> {
+    self.sys.disk(args.mount.as_deref()).map(f)
+  }
+
+  fn mem_placeholder(&self, name: &str, args: Args) -> Option<String> {
+    self.bytes_placeholder(name, ByteFormat::KiB, args)
+  }
+
+  /// Bytes per second, or bits with `bits`, `with_suffix` appends `/s` to the unit
+  fn rate_placeholder(&self, name: &str, args: Args) -> Option<String> {
+    let with_suffix = args.with_suffix;
+    let val = self.bytes_placeholder(name, ByteFormat::Bytes, args)?;
+
+    if with_suffix {
+      Some(format!("{val}/s"))
+    } else {
+      Some(val)
+    }
+  }
+
+  fn bytes_placeholder(&self, name: &str, unit: ByteFormat, args: Args) -> Option<String> {
+    let val = self.metric(name, &args)?;
+    // deltas can be negative
+    let sign = match val {
+      val if val < 0f64 => "-",
+      _ if args.number.sign => "+",
+      _ => "",
+    };
+    let bits = args.bits && !args.format.is_bits();
+    let val = if bits { val.abs() * 8f64 } else { val.abs() };
+    let val = (val.round() as u64)
+      .convert_to_display(unit, args.format)
+      .with_unit_range(args.min_unit, args.max_unit)
+      .with_fixed_width(args.fixed_width)
+      .with_number_format(NumberFormat { sign: false, ..args.number });
+
+    Some(match (args.with_suffix, bits) {
+      (true, true) => format!("{sign}{} {}", val.to_string_no_suffix(), val.unit().to_string().replace('B', "bit")),
+      (true, false) => format!("{sign}{val}"),
+      (false, _) => format!("{sign}{}", val.to_string_no_suffix()),
+    })
+  }
 }
 
 
@@ -248,7 +1068,25 @@ impl PlaceholderExpander for Data {
     fn custom(s: &String) -> PlaceholderFn {
       let s = s.clone();
 
-      Box::new(move |_, _| { s.clone() })
+      Box::new(move |_, _| { Some(s.clone()) })
+    }
+
+    if let Some(expr) = name.strip_prefix('=') {
+      let expr = expr.to_string();
+
+      return Some(Box::new(move |data, args| data.expr_placeholder(&expr, args)));
+    }
+
+    if self.macros.contains_key(name) {
+      let name = name.to_string();
+
+      return Some(Box::new(move |data, args| data.macro_placeholder(&name, &args.raw)));
+    }
+
+    if let Some(value) = self.items.borrow().last().and_then(|it| it.field(name)) {
+      let value = value.to_string();
+
+      return Some(Box::new(move |_, _| Some(value.clone())));
     }
 
     match self._get_placeholder(name) {
@@ -259,35 +1097,294 @@ impl PlaceholderExpander for Data {
     }
   }
 
+  /// Placeholders inside `each` select its current item, unless their own arguments say otherwise
   fn parse_args(&self, name: &str, args: &[&str]) -> Self::Args {
-    Args::from(name, args)
+    let items = self.items.borrow();
+    let item = items.last().map(|it| it.args.as_slice()).unwrap_or_default();
+    let mut out = Args::from(name, &item.iter().map(String::as_str).chain(args.iter().copied()).collect::<Vec<_>>(), self.number);
+
+    out.raw.drain(..item.len());
+    out
+  }
+
+  fn takes_raw_args(&self, name: &str) -> bool {
+    name == "each"
+  }
+
+  fn missing_value(&self) -> &str {
+    &self.missing
+  }
+}
+
+fn load_config(path: Option<PathBuf>) -> Result<Config, String> {
+  match path {
+    Some(path) => File::open(&path)
+      .map_err(|err| format!("Could not open config {path:?}: {err}"))
+      .and_then(|file| Config::from_read(BufReader::new(file))
+        .map_err(|err| format!("Could not read config {path:?}: {err:?}"))),
+    None => Config::from_str(DEFAULT_CONFIG).map_err(|err| format!("{err:?}")),
   }
 }
 
+fn watch(mut data: Data, interval: Duration, record: bool) {
+  let policy = data.retention_policy();
+  let mut last_compact = 0;
+  // the first refresh comes quickly, rates need a second sample
+  let mut wait = SAMPLE_INTERVAL;
+
+  loop {
+    std::thread::sleep(wait);
+    wait = interval;
+
+    data.refresh();
+    println!("{}", data.output());
+
+    if record {
+      let now = history::now();
+
+      if let Err(err) = data.record_history(now) {
+        log::error!("Could not write history to {:?}: {err}", data.history.path());
+      }
+
+      if now.saturating_sub(last_compact) >= COMPACT_INTERVAL {
+        if let Err(err) = data.history.compact(now, &policy) {
+          log::error!("Could not compact history at {:?}: {err}", data.history.path());
+        }
+
+        last_compact = now;
+      }
+    }
+  }
+}
+
+fn sensors(sys: &FixedSystem) {
+  let cpu = sys.cpu_temp_component().map(|it| it.label());
+  let width = sys.components().iter().map(|it| it.label().len()).max().unwrap_or_default();
+
+  for component in sys.components() {
+    let critical = component.critical()
+      .map(|it| format!("  critical {}°C", it.trim_trailing_zeros_with_precision(1)))
+      .unwrap_or_default();
+    let used_for_cpu = if cpu == Some(component.label()) { "  (cpu_temp)" } else { "" };
+
+    println!(
+      "{:<width$}  {:>6}°C  max {}°C{critical}{used_for_cpu}",
+      component.label(),
+      component.temperature().trim_trailing_zeros_with_precision(1),
+      component.max().trim_trailing_zeros_with_precision(1),
+    );
+  }
+}
+
+fn history(data: &Data, metric: &str, since: Duration, agg: &str) -> Result<(), String> {
+  let aggregates = agg
+    .split(',')
+    .map(Aggregate::from_str)
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let samples = data.history
+    .query(metric, history::now().saturating_sub(since.as_secs()))
+    .map_err(|err| format!("Could not read history from {:?}: {err}", data.history.path()))?;
+
+  if samples.is_empty() {
+    return Err(format!("No samples for '{metric}' in {:?}", data.history.path()));
+  }
+
+  for aggregate in aggregates {
+    let value = aggregate
+      .apply(samples.iter().map(|(_, value)| *value))
+      .unwrap_or(f64::NAN);
+
+    println!("{}={}", format!("{aggregate:?}").to_lowercase(), value.trim_trailing_zeros_with_precision(2));
+  }
+
+  Ok(())
+}
+
+/// Separators of `decimal_separator` and `thousands_separator`, otherwise of the locale,
+/// `group_thousands` groups every number
+fn number_format(config: &Config) -> NumberFormat {
+  let locale = NumericLocale::from_env();
+  let separator = |key: &str| config
+    .setting::<String>(key)
+    .and_then(|it| match it.as_str() {
+      "space" => Some(' '),
+      it => it.chars().next(),
+    });
+  let decimal_separator = separator("decimal_separator").unwrap_or(locale.decimal);
+  let thousands_separator = separator("thousands_separator")
+    .or(locale.thousands)
+    .unwrap_or(if decimal_separator == ',' { '.' } else { ',' });
+
+  NumberFormat {
+    decimal_separator,
+    thousands_separator,
+    grouping: config.setting("group_thousands").unwrap_or(false),
+    ..NumberFormat::default()
+  }
+}
+
+/// Whether any `each` placeholder iterates over processes, collections that are only known
+/// when expanding like ones from macro parameters might be processes too
+fn uses_processes(config: &Config) -> bool {
+  std::iter::once(&config.output)
+    .chain(config.sections.iter().map(|(_, body)| body))
+    .flat_map(|it| placeholders::find_placeholders(it, "${", "}"))
+    .any(|parts| match parts.as_slice() {
+      [name, collection, ..] if name == "each" => collection == "processes" || collection.contains('$'),
+      _ => false,
+    })
+}
+
+/// Everything the status line placeholders need, processes only when a template iterates over them
+fn status_system(config: &Config) -> FixedSystem {
+  let refreshes = RefreshKind::new()
+    .with_memory()
+    .with_cpu()
+    .with_components()
+    .with_components_list()
+    .with_networks()
+    .with_networks_list()
+    .with_disks()
+    .with_disks_list();
+  let mut sys = FixedSystem::new_with_specifics(if uses_processes(config) { refreshes.with_processes() } else { refreshes });
+
+  sys.set_cpu_temp_sensor(config.setting("cpu_temp_sensor"));
+  sys
+}
+
 fn main() {
+  let opts = Opts::parse();
+
   log::init(LogMode::Silent);
   set_color_mode(ColorMode::Always);
 
-  let sys = FixedSystem::new_with_specifics(
-    RefreshKind::new()
-      .with_memory()
-      .with_cpu()
-      .with_components()
-      .with_components_list()
-      .with_networks()
-      .with_networks_list()
-  );
-
-  let config = Config::from_str(r#"
-symbol=${fg|gray}
-sep=${symbol}|
-used=${fg|green}${mem_used|.2}
-total=${symbol}/ ${fg|blue}${mem_total} ${symbol}GiB
-usage=${symbol}(${fg|yellow}${mem_usage|.2|with_suffix}${symbol}%)
-cpu=${fg|red}${cpu_usage|.2}${symbol}% ${fg|magenta}${cpu_temp|.2}${symbol}°C
-output=${sep} ${cpu} ${sep} ${used} ${total} ${usage} ${sep} ${reset}
-"#).unwrap();
-  let str = Data::expand_placeholders_from(sys, config);
+  let config = match load_config(opts.config) {
+    Ok(config) => config,
+    Err(err) => {
+      eprintln!("{err}");
+      std::process::exit(1);
+    }
+  };
 
-  println!("{}", str);
+  match opts.command {
+    None => {
+      let mut data = Data::new(status_system(&config), config);
+
+      // rates and usages need a second sample
+      std::thread::sleep(SAMPLE_INTERVAL);
+      data.refresh();
+
+      println!("{}", data.output())
+    }
+    Some(Command::Watch { interval, record }) => watch(Data::new(status_system(&config), config), interval, record),
+    Some(Command::Top { interval }) => {
+      let sys = FixedSystem::new_with_specifics(RefreshKind::everything().without_users_list());
+
+      if let Err(err) = top::run(sys, top::Theme::from_config(&config), interval) {
+        eprintln!("{err}");
+        std::process::exit(1);
+      }
+    }
+    Some(Command::Sensors) => sensors(&status_system(&config)),
+    Some(Command::History { metric, since, agg }) => {
+      if let Err(err) = history(&Data::new(status_system(&config), config), &metric, since, &agg) {
+        eprintln!("{err}");
+        std::process::exit(1);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn disk(mount: &str, usage: f64) -> Item {
+    Item {
+      name: mount.to_string(),
+      fields: vec![("mount", mount.to_string(), None), ("usage", usage.to_string(), Some(usage))],
+      args: vec![format!("mount={mount}")],
+    }
+  }
+
+  fn each(options: &[&str], items: Vec<Item>) -> Vec<String> {
+    EachOptions::parse(options.iter().map(|it| it.to_string()))
+      .apply(items, |_, _| None)
+      .into_iter()
+      .map(|it| it.name)
+      .collect()
+  }
+
+  fn disks() -> Vec<Item> {
+    vec![disk("/", 69f64), disk("/home", 40f64), disk("/mnt/data", 88f64), disk("/boot", 12f64)]
+  }
+
+  #[test]
+  fn each_options_apply_in_order() {
+    assert_eq!(each(&["limit=1", "sort=-usage"], disks()), vec!["/mnt/data"]);
+    assert_eq!(each(&["sort=-usage", "limit=1"], disks()), vec!["/mnt/data"]);
+    assert_eq!(each(&["limit=2", "sort=usage", "filter=^/(home|boot|mnt)"], disks()), vec!["/boot", "/home"]);
+  }
+
+  #[test]
+  fn each_sorts_by_fields_and_metrics() {
+    assert_eq!(each(&["sort=mount"], disks()), vec!["/", "/boot", "/home", "/mnt/data"]);
+    assert_eq!(each(&["sort=-name"], disks()), vec!["/mnt/data", "/home", "/boot", "/"]);
+    assert_eq!(each(&["sort=nope"], disks()), vec!["/", "/home", "/mnt/data", "/boot"]);
+
+    let sorted = EachOptions::parse(vec!["sort=-disk_usage".to_string()])
+      .apply(disks(), |name, item| match name {
+        "disk_usage" if item.name != "/home" => item.field("usage")?.parse().ok(),
+        _ => None,
+      })
+      .into_iter()
+      .map(|it| it.name)
+      .collect::<Vec<_>>();
+
+    // missing values go last
+    assert_eq!(sorted, vec!["/mnt/data", "/", "/boot", "/home"]);
+  }
+
+  fn data(config: &str) -> Data {
+    Data::new(FixedSystem::new(), Config::from_str(config).unwrap())
+  }
+
+  #[test]
+  fn macros() {
+    let data = data("t(x) = ${env|$x|default=none}\nlabel(a, b) = $a=$b\noutput = x");
+
+    assert_eq!(data.expand_placeholders(r"[${t|NOPE\|default=injected}] [${t|A\}B}]"), "[none] [none]");
+    assert_eq!(data.expand_placeholders("${label|cpu|${t|NOPE}}"), "cpu=none");
+  }
+
+  #[test]
+  fn macros_using_themselves() {
+    let data = data("a(x) = <${b|$x}>\nb(x) = ${a|$x}\nc(x) = ${c|$x}\noutput = x");
+
+    assert_eq!(data.expand_placeholders("${a|1} ${c|2}"), "<> ");
+    assert!(data.expanding.borrow().is_empty());
+  }
+
+  #[test]
+  fn temperature_units() {
+    let unit = |arg: &str| Args::from("cpu_temp", &[arg], NumberFormat::default()).temperature;
+
+    assert_eq!(unit("f"), TemperatureUnit::Fahrenheit);
+    assert_eq!(unit("Kelvin"), TemperatureUnit::Kelvin);
+    assert_eq!(unit("c"), TemperatureUnit::Celsius);
+    assert_eq!(unit("kb"), TemperatureUnit::Celsius);
+    assert_eq!(Args::from("mem_used", &["kb"], NumberFormat::default()).format, ByteFormat::KB);
+  }
+
+  #[test]
+  fn processes_only_when_iterated() {
+    let uses = |str: &str| uses_processes(&Config::from_str(str).unwrap());
+
+    assert!(uses("output = ${each|processes|${process}}"));
+    assert!(uses("top = ${each|processes|${pid}|limit=3}\noutput = ${top}"));
+    assert!(uses("list(of) = ${each|$of|x}\noutput = ${list|processes}"));
+    assert!(!uses("# ${each|processes|x}\noutput = ${each|disks|${mount}}"));
+    assert!(!uses("processes = 3\noutput = ${processes} $${each|processes|x}"));
+  }
 }
@@ -0,0 +1,169 @@
+use std::str::FromStr;
+
+use sysinfo::{Component, ComponentExt, Disk, DiskExt, NetworkExt, NetworksExt, ProcessorExt, SystemExt};
+
+use crate::bytes::ByteFormat;
+use crate::fixed_system::FixedSystem;
+use crate::graph::Scale;
+
+/// Numeric values that can be recorded into history and aggregated,
+/// memory is in `KiB`, disks and networks are in bytes and bytes per second, usages are in percent, temperatures are in `°C` and frequencies in `MHz`.
+///
+/// Some metrics take a selector, like the core for `cpu_usage`,
+/// without one they return the overall value. Values that aren't finite, like usages of nothing, count as missing
+macro_rules! metrics {
+  ($($name:ident ($sys:ident, $selector:ident) $b:block)+) => {
+    $(
+      fn $name($sys: &FixedSystem, $selector: Option<&str>) -> Option<f64> { Some($b).filter(|it| it.is_finite()) }
+    )+
+
+    pub const METRICS: &[&str] = &[$(stringify!($name),)+];
+
+    pub fn sample(sys: &FixedSystem, name: &str, selector: Option<&str>) -> Option<f64> {
+      match name {
+        $(stringify!($name) => $name(sys, selector),)+
+        _ => None
+      }
+    }
+  };
+}
+
+metrics! {
+  mem_usage(sys, _s) { (sys.used_memory() as f64 / sys.total_memory() as f64) * 100f64 }
+  mem_used(sys, _s) { sys.used_memory() as f64 }
+  mem_free(sys, _s) { sys.free_memory() as f64 }
+  mem_available(sys, _s) { sys.available_memory() as f64 }
+  mem_total(sys, _s) { sys.total_memory() as f64 }
+  swap_used(sys, _s) { sys.used_swap() as f64 }
+  swap_total(sys, _s) { sys.total_swap() as f64 }
+  swap_free(sys, _s) { sys.free_swap() as f64 }
+  swap_usage(sys, _s) { (sys.used_swap() as f64 / sys.total_swap() as f64) * 100f64 }
+
+  cpu_usage(sys, core) {
+    match core {
+      Some(core) => sys.processors().get(usize::from_str(core).ok()?)?.cpu_usage() as f64,
+      None => sys.cpu().processor.cpu_usage() as f64,
+    }
+  }
+
+  cpu_freq(sys, core) {
+    match core {
+      Some(core) => sys.processors().get(usize::from_str(core).ok()?)?.frequency() as f64,
+      None => sys.cpu().processor.frequency() as f64,
+    }
+  }
+
+  cpu_temp(sys, _s) { sys.cpu().component?.temperature() as f64 }
+  cpu_critical_temp(sys, _s) { sys.cpu().component?.critical()? as f64 }
+  cpu_max_temp(sys, _s) { sys.cpu().component?.max() as f64 }
+
+  temp(sys, label) { component(sys, label)?.temperature() as f64 }
+  temp_max(sys, label) { component(sys, label)?.max() as f64 }
+  temp_critical(sys, label) { component(sys, label)?.critical()? as f64 }
+
+  disk_used(sys, mount) { used_space(sys.disk(mount)?) as f64 }
+
+  disk_free(sys, mount) { sys.disk(mount)?.available_space() as f64 }
+  disk_total(sys, mount) { sys.disk(mount)?.total_space() as f64 }
+
+  disk_usage(sys, mount) {
+    let disk = sys.disk(mount)?;
+
+    if disk.total_space() == 0 {
+      return None;
+    }
+
+    used_space(disk) as f64 / disk.total_space() as f64 * 100f64
+  }
+
+  disk_read(sys, dev) { sys.disk_rates(dev)?.read_bytes }
+  disk_write(sys, dev) { sys.disk_rates(dev)?.write_bytes }
+  disk_read_iops(sys, dev) { sys.disk_rates(dev)?.read_iops }
+  disk_write_iops(sys, dev) { sys.disk_rates(dev)?.write_iops }
+  disk_util(sys, dev) { sys.disk_rates(dev)?.util }
+
+  net_rx(sys, iface) { sys.network_rate(iface, NetworkExt::received)? }
+  net_tx(sys, iface) { sys.network_rate(iface, NetworkExt::transmitted)? }
+  net_rx_total(sys, iface) { sys.network_total(iface, NetworkExt::total_received)? as f64 }
+  net_tx_total(sys, iface) { sys.network_total(iface, NetworkExt::total_transmitted)? as f64 }
+  net_rx_packets(sys, iface) { sys.network_total(iface, NetworkExt::total_packets_received)? as f64 }
+  net_tx_packets(sys, iface) { sys.network_total(iface, NetworkExt::total_packets_transmitted)? as f64 }
+  net_rx_errors(sys, iface) { sys.network_total(iface, NetworkExt::total_errors_on_received)? as f64 }
+  net_tx_errors(sys, iface) { sys.network_total(iface, NetworkExt::total_errors_on_transmitted)? as f64 }
+}
+
+/// Space in use, some network and overlay mounts report more available than total space
+pub fn used_space(disk: &Disk) -> u64 {
+  disk.total_space().saturating_sub(disk.available_space())
+}
+
+/// Sensor by its exact label, or the CPU's without one
+fn component<'a>(sys: &'a FixedSystem, label: Option<&str>) -> Option<&'a Component> {
+  match label {
+    Some(label) => sys.components().iter().find(|it| it.label() == label),
+    None => sys.cpu_temp_component(),
+  }
+}
+
+/// Every selector a metric can take
+pub fn selectors(sys: &FixedSystem, name: &str) -> Vec<String> {
+  match name {
+    "cpu_usage" | "cpu_freq" => (0..sys.processors().len()).map(|it| it.to_string()).collect(),
+    "temp" | "temp_max" | "temp_critical" => sys.components().iter().map(|it| it.label().to_string()).collect(),
+    "disk_read" | "disk_write" | "disk_read_iops" | "disk_write_iops" | "disk_util" => sys.disk_devices(),
+    name if name.starts_with("net_") => sys.networks().iter().map(|(name, _)| name.to_string()).collect(),
+    name if name.starts_with("disk_") => sys.disks().iter().map(|it| it.mount_point().to_string_lossy().into_owned()).collect(),
+    _ => vec![]
+  }
+}
+
+/// Name a metric is recorded as, `name@selector` when it has a selector
+pub fn key(name: &str, selector: Option<&str>) -> String {
+  match selector {
+    Some(selector) => format!("{name}@{selector}"),
+    None => name.to_string(),
+  }
+}
+
+/// Samples every metric with and without each of its selectors
+pub fn sample_all(sys: &FixedSystem) -> Vec<(String, f64)> {
+  let mut out = vec![];
+
+  for name in METRICS {
+    if let Some(value) = sample(sys, name, None) {
+      out.push((name.to_string(), value));
+    }
+
+    for selector in selectors(sys, name) {
+      if let Some(value) = sample(sys, name, Some(&selector)) {
+        out.push((key(name, Some(&selector)), value));
+      }
+    }
+  }
+
+  out
+}
+
+/// The range a metric can be in, if it has one
+pub fn scale(sys: &FixedSystem, name: &str, selector: Option<&str>) -> Option<Scale> {
+  match name {
+    name if name.ends_with("_usage") || name.ends_with("_util") => Some(Scale::new(0f64, 100f64)),
+    name if name.starts_with("mem_") => Some(Scale::new(0f64, sys.total_memory() as f64)),
+    name if name.starts_with("swap_") => Some(Scale::new(0f64, sys.total_swap() as f64)),
+    "cpu_temp" | "cpu_max_temp" => Some(Scale::new(0f64, cpu_critical_temp(sys, None)?)),
+    "temp" | "temp_max" => Some(Scale::new(0f64, temp_critical(sys, selector)?)),
+    "disk_used" | "disk_free" => Some(Scale::new(0f64, disk_total(sys, selector)?)),
+    _ => None
+  }
+}
+
+/// Unit of metrics that are sizes, so limits like `warn=512MiB` can be converted to it
+pub fn byte_unit(name: &str) -> Option<ByteFormat> {
+  match name {
+    name if name.ends_with("_usage") => None,
+    name if name.starts_with("mem_") || name.starts_with("swap_") => Some(ByteFormat::KiB),
+    "disk_used" | "disk_free" | "disk_total" | "disk_read" | "disk_write" => Some(ByteFormat::Bytes),
+    "net_rx" | "net_tx" | "net_rx_total" | "net_tx_total" => Some(ByteFormat::Bytes),
+    _ => None
+  }
+}
@@ -0,0 +1,183 @@
+use std::ffi::CStr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+/// Route to `0.0.0.0/0` out of `/proc/net/route`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultRoute {
+  pub iface: String,
+  pub gateway: Ipv4Addr,
+  pub metric: u32,
+}
+
+/// Parses `/proc/net/route`, where addresses are little endian hex,
+/// and picks the default route with the lowest metric, optionally only out of `iface`
+pub fn parse_default_route(str: &str, iface: Option<&str>) -> Option<DefaultRoute> {
+  str
+    .lines()
+    .skip(1)
+    .filter_map(|line| {
+      let fields = line.split_whitespace().collect::<Vec<_>>();
+      let hex = |index: usize| u32::from_str_radix(fields.get(index)?, 16).ok();
+      let (destination, gateway, metric, mask) = (hex(1)?, hex(2)?, fields.get(6)?.parse().ok()?, hex(7)?);
+
+      if destination != 0 || mask != 0 || iface.filter(|it| fields.first() != Some(it)).is_some() {
+        return None;
+      }
+
+      Some(DefaultRoute {
+        iface: fields.first()?.to_string(),
+        gateway: Ipv4Addr::from(u32::from_be(gateway)),
+        metric,
+      })
+    })
+    .min_by_key(|it| it.metric)
+}
+
+pub fn default_route(iface: Option<&str>) -> Option<DefaultRoute> {
+  parse_default_route(&std::fs::read_to_string("/proc/net/route").ok()?, iface)
+}
+
+/// Loopback, bridges, tunnels, veths and the like have no device behind them
+pub fn is_virtual(iface: &str) -> bool {
+  let net = Path::new("/sys/class/net");
+
+  if !net.exists() {
+    return iface == "lo";
+  }
+
+  !net.join(iface).join("device").exists()
+}
+
+fn sys_class_net(iface: &str, file: &str) -> Option<String> {
+  let path = PathBuf::from("/sys/class/net").join(iface).join(file);
+
+  std::fs::read_to_string(path).ok().map(|it| it.trim().to_string())
+}
+
+pub fn mac(iface: &str) -> Option<String> {
+  sys_class_net(iface, "address")
+}
+
+pub fn mtu(iface: &str) -> Option<u32> {
+  sys_class_net(iface, "mtu")?.parse().ok()
+}
+
+/// `up` or `down`, interfaces without carrier detection like `lo` or tunnels report `unknown`
+/// as their operational state, so for those the administrative `IFF_UP` flag decides
+pub fn is_up(iface: &str) -> Option<bool> {
+  match sys_class_net(iface, "operstate")?.as_str() {
+    "up" => Some(true),
+    "unknown" => {
+      let flags = sys_class_net(iface, "flags")?;
+      let flags = u32::from_str_radix(flags.trim_start_matches("0x"), 16).ok()?;
+
+      Some(flags & libc::IFF_UP as u32 != 0)
+    }
+    _ => Some(false),
+  }
+}
+
+/// Every address of an interface out of `getifaddrs`
+pub fn addresses(iface: &str) -> Vec<IpAddr> {
+  let mut out = vec![];
+  let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+
+  if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+    log::warn!("Could not list interface addresses: {}", std::io::Error::last_os_error());
+    return out;
+  }
+
+  let mut next = addrs;
+
+  // the list and everything in it stays valid until freeifaddrs
+  while let Some(ifaddr) = unsafe { next.as_ref() } {
+    next = ifaddr.ifa_next;
+
+    if ifaddr.ifa_addr.is_null() || unsafe { CStr::from_ptr(ifaddr.ifa_name) }.to_bytes() != iface.as_bytes() {
+      continue;
+    }
+
+    match unsafe { (*ifaddr.ifa_addr).sa_family } as i32 {
+      libc::AF_INET => {
+        let addr = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_in) };
+
+        out.push(IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))));
+      }
+      libc::AF_INET6 => {
+        let addr = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_in6) };
+
+        out.push(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)));
+      }
+      _ => {}
+    }
+  }
+
+  unsafe { libc::freeifaddrs(addrs) };
+  out
+}
+
+/// First address of an interface, for IPv6 global addresses are preferred over link-local ones
+pub fn address(iface: &str, ipv6: bool) -> Option<IpAddr> {
+  let is_link_local = |addr: &IpAddr| match addr {
+    IpAddr::V6(addr) => addr.segments()[0] & 0xffc0 == 0xfe80,
+    IpAddr::V4(_) => false,
+  };
+  let addresses = addresses(iface)
+    .into_iter()
+    .filter(|it| it.is_ipv6() == ipv6)
+    .collect::<Vec<_>>();
+
+  addresses
+    .iter()
+    .find(|it| !is_link_local(it))
+    .or_else(|| addresses.first())
+    .copied()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HEADER: &str = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT";
+
+  fn routes(lines: &[&str]) -> String {
+    std::iter::once(HEADER).chain(lines.iter().copied()).collect::<Vec<_>>().join("\n")
+  }
+
+  #[test]
+  fn no_routes() {
+    assert_eq!(parse_default_route(HEADER, None), None);
+    assert_eq!(parse_default_route("", None), None);
+    // not a default route, the mask isn't 0
+    assert_eq!(parse_default_route(&routes(&["eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0"]), None), None);
+  }
+
+  #[test]
+  fn default_routes() {
+    let str = routes(&[
+      "wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0",
+      "eth0\t00000000\tFE00000A\t0003\t0\t0\t100\t00000000\t0\t0\t0",
+      "eth0\t0000000A\t00000000\t0001\t0\t0\t100\t000000FF\t0\t0\t0",
+    ]);
+
+    assert_eq!(parse_default_route(&str, None), Some(DefaultRoute {
+      iface: "eth0".to_string(),
+      gateway: Ipv4Addr::new(10, 0, 0, 254),
+      metric: 100,
+    }));
+    assert_eq!(parse_default_route(&str, Some("wlan0")), Some(DefaultRoute {
+      iface: "wlan0".to_string(),
+      gateway: Ipv4Addr::new(192, 168, 1, 1),
+      metric: 600,
+    }));
+    assert_eq!(parse_default_route(&str, Some("eth1")), None);
+  }
+
+  #[test]
+  fn invalid_lines_are_skipped() {
+    let str = routes(&["eth0\tzz\t0101A8C0", "wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0"]);
+
+    assert_eq!(parse_default_route(&str, None).map(|it| it.iface), Some("wlan0".to_string()));
+  }
+}
@@ -0,0 +1,97 @@
+use std::ffi::{CStr, CString};
+use std::io::Read;
+use std::path::Path;
+
+extern "C" {
+  // not in the libc crate version this uses
+  fn strftime(s: *mut libc::c_char, max: libc::size_t, format: *const libc::c_char, tm: *const libc::tm) -> libc::size_t;
+}
+
+/// Local time formatted like `strftime`, `None` when the format is invalid or the result is too long
+pub fn format_time(format: &str) -> Option<String> {
+  let format = CString::new(format).ok()?;
+  let now = unsafe { libc::time(std::ptr::null_mut()) };
+  let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+
+  if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+    log::warn!("Could not get the local time: {}", std::io::Error::last_os_error());
+    return None;
+  }
+
+  let mut buf = vec![0u8; 256];
+  let len = unsafe { strftime(buf.as_mut_ptr() as *mut libc::c_char, buf.len(), format.as_ptr(), &tm) };
+
+  // 0 is also what an empty format gives
+  if len == 0 && !format.as_bytes().is_empty() {
+    log::warn!("Time format {format:?} is invalid or longer than {} bytes", buf.len());
+    return None;
+  }
+
+  buf.truncate(len);
+  Some(String::from_utf8_lossy(&buf).into_owned())
+}
+
+pub fn hostname() -> Option<String> {
+  let mut buf = [0u8; 256];
+
+  if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+    log::warn!("Could not get the hostname: {}", std::io::Error::last_os_error());
+    return None;
+  }
+
+  let name = CStr::from_bytes_until_nul(&buf).ok()?;
+
+  Some(name.to_string_lossy().into_owned())
+}
+
+/// `$USER`, or the name of the effective user when it isn't set
+pub fn user() -> Option<String> {
+  if let Some(user) = std::env::var("USER").ok().filter(|it| !it.is_empty()) {
+    return Some(user);
+  }
+
+  // the entry stays valid until the next getpw* call
+  let passwd = unsafe { libc::getpwuid(libc::geteuid()).as_ref() }?;
+
+  Some(unsafe { CStr::from_ptr(passwd.pw_name) }.to_string_lossy().into_owned())
+}
+
+/// Up to `limit` bytes of a file, invalid UTF-8 is replaced
+pub fn read_file(path: &Path, limit: u64) -> Option<String> {
+  let mut buf = vec![];
+
+  std::fs::File::open(path)
+    .and_then(|file| file.take(limit).read_to_end(&mut buf))
+    .map_err(|err| log::warn!("Could not read {path:?}: {err}"))
+    .ok()?;
+
+  Some(String::from_utf8_lossy(&buf).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn empty_time_format() {
+    assert_eq!(format_time(""), Some(String::new()));
+  }
+
+  #[test]
+  fn time_formats() {
+    assert_eq!(format_time("%%"), Some("%".to_string()));
+    assert_eq!(format_time("%Y").map(|it| it.len()), Some(4));
+    assert_eq!(format_time("a\0b"), None);
+  }
+
+  #[test]
+  fn file_size_cap() {
+    let path = std::env::temp_dir().join(format!("sysusage-{}-read-file", std::process::id()));
+
+    std::fs::write(&path, "0123456789").unwrap();
+    assert_eq!(read_file(&path, 4), Some("0123".to_string()));
+    assert_eq!(read_file(&path, 100), Some("0123456789".to_string()));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read_file(&path, 4), None);
+  }
+}
@@ -1,7 +1,27 @@
 use std::fmt::Debug;
 use std::ops::Add;
 
-pub type PlaceholderFn<T, A> = Box<dyn Fn(&T, A) -> String>;
+use crate::filters::TextFilters;
+
+/// Returns `None` when the value is missing, like a sensor that isn't there
+pub type PlaceholderFn<T, A> = Box<dyn Fn(&T, A) -> Option<String>>;
+
+/// What placeholder functions can return, so the ones that always have a value don't need to wrap it
+pub trait PlaceholderValue {
+  fn into_value(self) -> Option<String>;
+}
+
+impl PlaceholderValue for String {
+  fn into_value(self) -> Option<String> {
+    Some(self)
+  }
+}
+
+impl PlaceholderValue for Option<String> {
+  fn into_value(self) -> Option<String> {
+    self
+  }
+}
 
 pub trait PlaceholderExpander where Self: 'static {
   type Args: Debug;
@@ -11,70 +31,468 @@ pub trait PlaceholderExpander where Self: 'static {
 
   fn placeholder_prefix(&self) -> &str { "${" }
   fn placeholder_suffix(&self) -> &str { "}" }
+  /// Shown for missing values of placeholders without `default=`
+  fn missing_value(&self) -> &str { "" }
+  /// Placeholders that get their arguments without nested placeholders expanded, like templates
+  fn takes_raw_args(&self, _name: &str) -> bool { false }
 
   fn expand_placeholders(&self, str: &str) -> String {
+    self.expand_placeholders_tracked(str).0
+  }
+
+  /// `None` when any placeholder is missing its value
+  fn expand_placeholders_if_complete(&self, str: &str) -> Option<String> {
+    match self.expand_placeholders_tracked(str) {
+      (out, true) => Some(out),
+      (_, false) => None,
+    }
+  }
+
+  /// Expands every placeholder, along with whether all of them had a value.
+  /// The prefix with its first character doubled like `$${` is a literal prefix
+  fn expand_placeholders_tracked(&self, str: &str) -> (String, bool) {
     let mut out = String::with_capacity(str.len() * 2);
+    let mut complete = true;
     let prefix = self.placeholder_prefix();
     let suffix = self.placeholder_suffix();
-    let mut idx = 0;
-
-    while idx < str.len() {
-      let remaining = &str[idx..];
-
-      match remaining.find(prefix) {
-        Some(start) => {
-          let value = &remaining[start + prefix.len()..];
-
-          match value.find(suffix) {
-            Some(len) => {
-              let placeholder_raw = &value[..len];
-              let placeholder_args = placeholder_raw.split("|").collect::<Vec<_>>();
-              let placeholder_len = len + suffix.len() + 1;
-              let name = placeholder_args.get(0).unwrap_or(&"");
-              let args = placeholder_args.get(1..).unwrap_or_default();
-              let args = self.parse_args(name, args);
-
-              log::debug!("Placeholder \"{placeholder_raw}\" with arguments {args:?} at index {idx}, +{start} (index {}) from last placeholder", idx + start);
-
-              out.push_str(&str[idx..idx + start]);
-
-              let value = match self.get_placeholder(name) {
-                Some(f) => f(self, args),
-                None => {
-                  log::warn!("Placeholder \"{name}\" does not exit");
-                  String::with_capacity(placeholder_len * 2)
-                    .add(prefix)
-                    .add(name)
-                    .add(suffix)
-                }
-              };
-
-              out.push_str(value.as_str());
-
-              idx += start + placeholder_len;
-            }
-            None => {
-              let last_suffix = remaining.find(suffix).unwrap_or(0);
-              let remaining = &remaining[last_suffix..];
-              let next_prefix = remaining.find(prefix).unwrap_or(remaining.len());
-
-              log::warn!("Missing suffix for placeholder at index '{}'", idx + start);
-
-              out.push_str(&remaining[last_suffix..next_prefix]);
-
-              idx += start;
-            }
-          }
+    let escape = prefix.chars().next().unwrap_or_default();
+    let mut rest = str;
+
+    while let Some(start) = rest.find(prefix) {
+      let (before, after) = (&rest[..start], &rest[start + prefix.len()..]);
+
+      if let Some(before) = before.strip_suffix(escape) {
+        out.push_str(before);
+        out.push_str(prefix);
+        rest = after;
+        continue;
+      }
+
+      out.push_str(before);
+
+      match split_placeholder(after, prefix, suffix) {
+        Some((parts, len)) => {
+          out.push_str(&self.expand_placeholder(&parts, &mut complete));
+          rest = &after[len..];
         }
         None => {
-          out.push_str(remaining);
-          idx = str.len()
+          log::warn!("Missing suffix for placeholder at index {}", str.len() - rest.len() + start);
+          out.push_str(prefix);
+          rest = after;
+        }
+      }
+    }
+
+    out.push_str(rest);
+    (out, complete)
+  }
+
+  /// Value of a placeholder out of its name and arguments, unknown placeholders are left as they are.
+  /// Placeholders nested in the name and arguments are expanded first, unless it takes raw arguments
+  fn expand_placeholder(&self, parts: &[String], complete: &mut bool) -> String {
+    let mut raw = false;
+    let parts = parts
+      .iter()
+      .enumerate()
+      .map(|(index, it)| {
+        if raw {
+          return it.clone();
+        }
+
+        let (part, part_complete) = self.expand_placeholders_tracked(it);
+
+        *complete &= part_complete;
+        raw = index == 0 && self.takes_raw_args(&part);
+        part
+      })
+      .collect::<Vec<_>>();
+    let name = parts.first().map(String::as_str).unwrap_or_default();
+    let args = parts
+      .iter()
+      .skip(1)
+      .map(String::as_str)
+      .collect::<Vec<_>>();
+    let filters = TextFilters::from_args(&args);
+    let default = args.iter().find_map(|it| it.strip_prefix("default="));
+    let parsed = self.parse_args(name, &args);
+
+    log::debug!("Placeholder \"{name}\" with arguments {parsed:?}");
+
+    match self.get_placeholder(name) {
+      Some(f) => match f(self, parsed) {
+        Some(value) => filters.apply(value),
+        None => {
+          *complete = false;
+          filters.apply(default.unwrap_or_else(|| self.missing_value()).to_string())
+        }
+      },
+      None => {
+        log::warn!("Placeholder \"{name}\" does not exist");
+        String::with_capacity(name.len() * 2)
+          .add(self.placeholder_prefix())
+          .add(name)
+          .add(self.placeholder_suffix())
+      }
+    }
+  }
+}
+
+/// Name and arguments of every placeholder in a string including nested ones, without expanding anything
+pub fn find_placeholders(str: &str, prefix: &str, suffix: &str) -> Vec<Vec<String>> {
+  let escape = prefix.chars().next().unwrap_or_default();
+  let mut out = vec![];
+  let mut rest = str;
+
+  while let Some(start) = rest.find(prefix) {
+    let (before, after) = (&rest[..start], &rest[start + prefix.len()..]);
+
+    rest = after;
+
+    if before.ends_with(escape) {
+      continue;
+    }
+
+    if let Some((parts, len)) = split_placeholder(after, prefix, suffix) {
+      out.extend(parts.iter().flat_map(|it| find_placeholders(it, prefix, suffix)));
+      out.push(parts);
+      rest = &after[len..];
+    }
+  }
+
+  out
+}
+
+/// Splits the inside of a placeholder at `|` into its name and arguments, up to the matching suffix.
+/// Arguments starting with a double quote can contain `|` and the suffix up to the closing one, outside of them `\` escapes
+/// `|`, `"`, `\` and the suffix's first character, any other backslash is kept for regexes like `\s`.
+/// Nested placeholders are kept as they are to be expanded later, the ones in quotes get escaped so they stay literal.
+/// Returns the parts and the length including the suffix, `None` when there is no suffix
+fn split_placeholder(str: &str, prefix: &str, suffix: &str) -> Option<(Vec<String>, usize)> {
+  let escape = prefix.chars().next().unwrap_or_default();
+  let escapable = |char: char| matches!(char, '|' | '"' | '\\') || suffix.starts_with(char);
+  let mut parts = vec![String::new()];
+  let mut quoted = false;
+  let mut chars = str.char_indices().peekable();
+
+  while let Some((idx, char)) = chars.next() {
+    let part = parts.last_mut()?;
+    let rest = &str[idx..];
+
+    // an escaped prefix like `$${` is kept as it is
+    if char == escape && rest[char.len_utf8()..].starts_with(prefix) {
+      let end = idx + char.len_utf8() + prefix.len();
+
+      part.push_str(&str[idx..end]);
+      while chars.next_if(|(next, _)| *next < end).is_some() {}
+      continue;
+    }
+
+    match char {
+      _ if quoted && rest.starts_with(prefix) => {
+        part.push(escape);
+        part.push(char);
+      }
+      _ if !quoted && rest.starts_with(prefix) => {
+        let (_, len) = split_placeholder(&rest[prefix.len()..], prefix, suffix)?;
+        let end = idx + prefix.len() + len;
+
+        part.push_str(&str[idx..end]);
+        while chars.next_if(|(next, _)| *next < end).is_some() {}
+      }
+      '\\' => match chars.next_if(|(_, next)| escapable(*next)) {
+        Some((_, next)) => part.push(next),
+        None => part.push(char),
+      },
+      '"' if quoted => quoted = false,
+      // quotes elsewhere are kept, like in the selectors of expressions `${= disk_usage@"/"}`
+      '"' if part.is_empty() => quoted = true,
+      _ if quoted => part.push(char),
+      '|' => parts.push(String::new()),
+      _ if str[idx..].starts_with(suffix) => return Some((parts, idx + suffix.len())),
+      _ => part.push(char),
+    }
+  }
+
+  None
+}
+
+/// Section with parameters like `gauge(label, value) = $1: $2`, invoked like `${gauge|CPU|${cpu_usage}}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macro {
+  pub name: String,
+  pub params: Vec<String>,
+  pub body: String,
+}
+
+impl Macro {
+  /// Parses a section name like `gauge(label, value)`, `None` for plain sections
+  pub fn parse(key: &str, body: &str) -> Option<Result<Self, String>> {
+    let (name, params) = key.strip_suffix(')')?.split_once('(')?;
+    let name = name.trim();
+    let params = params
+      .split(',')
+      .map(|it| it.trim().to_string())
+      .filter(|it| !it.is_empty())
+      .collect::<Vec<_>>();
+
+    if name.is_empty() || !name.chars().all(|it| it.is_alphanumeric() || it == '_') {
+      return Some(Err(format!("Invalid macro name in '{key}'")));
+    }
+
+    if let Some(param) = params.iter().find(|it| !it.chars().all(|it| it.is_alphanumeric() || it == '_')) {
+      return Some(Err(format!("Invalid parameter '{param}' of macro '{name}'")));
+    }
+
+    Some(Ok(Self {
+      name: name.to_string(),
+      params,
+      body: body.to_string(),
+    }))
+  }
+
+  /// The body with `$1`, `$2` and so on or `$param` replaced by the arguments, these get escaped so
+  /// placeholders in them aren't expanded a second time, and inside placeholders so they stay a single argument
+  pub fn substitute(&self, args: &[String], prefix: &str, suffix: &str) -> String {
+    if args.len() != self.params.len() {
+      log::warn!("Macro '{}' takes {} arguments but got {}", self.name, self.params.len(), args.len());
+    }
+
+    let escape = prefix.chars().next().unwrap_or_default();
+    let arg = |index: usize, nested: bool| {
+      let value = args.get(index).map(String::as_str).unwrap_or_default();
+      let mut out = String::with_capacity(value.len() * 2);
+
+      for char in value.chars() {
+        if nested && (matches!(char, '\\' | '|' | '"') || suffix.starts_with(char)) {
+          out.push('\\');
         }
+
+        out.push(char);
       }
 
-      idx += 1;
+      out.replace(prefix, &format!("{escape}{prefix}"))
+    };
+    let body = self.body.as_str();
+    let mut out = String::with_capacity(body.len() * 2);
+    // placeholders the body is inside of, and whether it's in a quoted argument of the innermost one
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((idx, char)) = chars.next() {
+      let rest = &body[idx..];
+      let mut skip_to = |end: usize| while chars.next_if(|(next, _)| *next < end).is_some() {};
+
+      if rest.starts_with("$$") {
+        // keeps escapes like `$${`
+        out.push_str("$$");
+        skip_to(idx + 2);
+      } else if char == '$' && !rest.starts_with(prefix) {
+        let word = &rest[1..];
+        let len = word
+          .find(|it: char| !(it.is_alphanumeric() || it == '_'))
+          .unwrap_or(word.len());
+        let digits = word[..len].find(|it: char| !it.is_ascii_digit()).unwrap_or(len);
+
+        if digits > 0 {
+          out.push_str(&arg(word[..digits].parse::<usize>().unwrap_or_default().saturating_sub(1), depth > 0));
+          skip_to(idx + 1 + digits);
+        } else if let Some(index) = self.params.iter().position(|it| *it == word[..len]) {
+          out.push_str(&arg(index, depth > 0));
+          skip_to(idx + 1 + len);
+        } else {
+          out.push(char);
+        }
+      } else if depth > 0 && char == '\\' {
+        out.push_str(&rest[..char.len_utf8() + rest[1..].chars().next().map_or(0, char::len_utf8)]);
+        chars.next();
+      } else if depth > 0 && char == '"' && (quoted || body[..idx].ends_with('|') || body[..idx].ends_with(prefix)) {
+        quoted = !quoted;
+        out.push(char);
+      } else if !quoted && rest.starts_with(prefix) {
+        depth += 1;
+        out.push_str(prefix);
+        skip_to(idx + prefix.len());
+      } else if !quoted && depth > 0 && rest.starts_with(suffix) {
+        depth -= 1;
+        out.push_str(suffix);
+        skip_to(idx + suffix.len());
+      } else {
+        out.push(char);
+      }
     }
 
     out
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Test;
+
+  impl PlaceholderExpander for Test {
+    type Args = Vec<String>;
+
+    fn get_placeholder(&self, name: &str) -> Option<PlaceholderFn<Self, Self::Args>> {
+      match name {
+        "a" => Some(Box::new(|_, _| Some("A".to_string()))),
+        "ä" => Some(Box::new(|_, _| Some("Ä".to_string()))),
+        "args" => Some(Box::new(|_, args: Vec<String>| Some(args.join(",")))),
+        "missing" => Some(Box::new(|_, _| None)),
+        _ => match name.strip_prefix('=') {
+          Some(expr) => {
+            let expr = expr.to_string();
+
+            Some(Box::new(move |_, _| {
+              crate::expr::eval(&expr, &|name, selector| match (name, selector) {
+                ("disk_usage", Some("/")) => Some(68.74),
+                _ => None,
+              }).ok().map(|it| it.to_string())
+            }))
+          }
+          None => None,
+        },
+      }
+    }
+
+    fn parse_args(&self, _name: &str, args: &[&str]) -> Self::Args {
+      args.iter().map(|it| it.to_string()).collect()
+    }
+  }
+
+  fn expand(str: &str) -> String {
+    Test.expand_placeholders(str)
+  }
+
+  #[test]
+  fn plain() {
+    assert_eq!(expand("x ${a} y"), "x A y");
+    assert_eq!(expand("${a}${a}"), "AA");
+    assert_eq!(expand("no placeholders"), "no placeholders");
+  }
+
+  #[test]
+  fn escaped_prefix() {
+    assert_eq!(expand("$${a}"), "${a}");
+    assert_eq!(expand("$${a} ${a}"), "${a} A");
+    assert_eq!(expand("${args|$${a}}"), "${a}");
+  }
+
+  #[test]
+  fn escapes() {
+    assert_eq!(expand(r"${args|x\|y|z}"), "x|y,z");
+    assert_eq!(expand(r"${args|a\}b}"), "a}b");
+    assert_eq!(expand(r#"${args|\"a\"}"#), r#""a""#);
+    assert_eq!(expand(r"${args|\\}"), r"\");
+    assert_eq!(expand(r"${args|\s+}"), r"\s+");
+  }
+
+  #[test]
+  fn unterminated() {
+    assert_eq!(expand("x ${a"), "x ${a");
+    assert_eq!(expand("${a} ${args|${a}"), "A ${args|A");
+    assert_eq!(expand("${"), "${");
+  }
+
+  #[test]
+  fn unknown() {
+    assert_eq!(expand("${nope}"), "${nope}");
+  }
+
+  #[test]
+  fn quoted() {
+    assert_eq!(expand(r#"${args|"x|y"|z}"#), "x|y,z");
+    assert_eq!(expand(r#"${args|"}"}"#), "}");
+    assert_eq!(expand(r#"${args|"${a}"}"#), "${a}");
+    assert_eq!(expand(r#"${args|a"b"}"#), r#"a"b""#);
+  }
+
+  #[test]
+  fn nested() {
+    assert_eq!(expand("${args|${a}|b}"), "A,b");
+    assert_eq!(expand("${args|${args|${a}}}"), "A");
+  }
+
+  #[test]
+  fn multibyte() {
+    assert_eq!(expand("ä ${args|ö|ü} 日本"), "ä ö,ü 日本");
+    assert_eq!(expand(r"${args|日本\|ä}"), "日本|ä");
+    assert_eq!(expand("${ä}${a}"), "ÄA");
+    assert_eq!(expand("ä ${ä"), "ä ${ä");
+  }
+
+  #[test]
+  fn missing() {
+    assert_eq!(Test.expand_placeholders_if_complete("${missing}"), None);
+    assert_eq!(Test.expand_placeholders_if_complete("${a}"), Some("A".to_string()));
+    assert_eq!(expand("${missing|default=x}"), "x");
+  }
+
+  #[test]
+  fn finds_placeholders() {
+    let found = find_placeholders(r#"${a} $${b} ${args|${c|x}|"${d}"} ${e"#, "${", "}");
+
+    assert_eq!(found, vec![
+      vec!["a".to_string()],
+      vec!["c".to_string(), "x".to_string()],
+      vec!["args".to_string(), "${c|x}".to_string(), "$${d}".to_string()],
+    ]);
+  }
+
+  fn gauge() -> Macro {
+    Macro::parse("gauge(label, value)", "$label: ${args|$2|$label}").unwrap().unwrap()
+  }
+
+  #[test]
+  fn parses_macros() {
+    assert_eq!(gauge(), Macro {
+      name: "gauge".to_string(),
+      params: vec!["label".to_string(), "value".to_string()],
+      body: "$label: ${args|$2|$label}".to_string(),
+    });
+    assert_eq!(Macro::parse("none( )", "x").unwrap().unwrap().params, Vec::<String>::new());
+    assert_eq!(Macro::parse("plain", "x"), None);
+    assert!(Macro::parse("bad name(x)", "x").unwrap().is_err());
+    assert!(Macro::parse("(x)", "x").unwrap().is_err());
+    assert!(Macro::parse("bad(x-y)", "x").unwrap().is_err());
+  }
+
+  #[test]
+  fn substitutes_arguments() {
+    let args = |args: &[&str]| args.iter().map(|it| it.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(gauge().substitute(&args(&["CPU", "5"]), "${", "}"), "CPU: ${args|5|CPU}");
+    assert_eq!(expand(&gauge().substitute(&args(&["CPU", "5"]), "${", "}")), "CPU: 5,CPU");
+    // `$$` and unknown parameters are kept
+    assert_eq!(Macro::parse("m(x)", "$$x $y $x").unwrap().unwrap().substitute(&args(&["1"]), "${", "}"), "$$x $y 1");
+  }
+
+  #[test]
+  fn escapes_arguments() {
+    let expand_gauge = |label: &str| expand(&gauge().substitute(&[label.to_string(), "v".to_string()], "${", "}"));
+
+    assert_eq!(expand_gauge("a|default=b"), "a|default=b: v,a|default=b");
+    assert_eq!(expand_gauge("a}b"), "a}b: v,a}b");
+    assert_eq!(expand_gauge(r#"a\"b"#), r#"a\"b: v,a\"b"#);
+    assert_eq!(expand_gauge("${a}"), "${a}: v,${a}");
+
+    let quoted = Macro::parse("q(x)", r#"${args|"$x"|b}"#).unwrap().unwrap();
+
+    assert_eq!(expand(&quoted.substitute(&["}|\"".to_string()], "${", "}")), r#"}|",b"#);
+  }
+
+  #[test]
+  fn wrong_number_of_arguments() {
+    // missing arguments are empty and extra ones are ignored, both get logged
+    assert_eq!(gauge().substitute(&["CPU".to_string()], "${", "}"), "CPU: ${args||CPU}");
+    assert_eq!(gauge().substitute(&["a".to_string(), "b".to_string(), "c".to_string()], "${", "}"), "a: ${args|b|a}");
+  }
+
+  #[test]
+  fn quoted_selector_in_expression() {
+    assert_eq!(expand(r#"${= disk_usage@"/" }"#), "68.74");
+    assert_eq!(expand(r#"${=disk_usage@"/" * 2}"#), "137.48");
+  }
+}
@@ -0,0 +1,50 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TemperatureUnit {
+  Celsius,
+  Fahrenheit,
+  Kelvin,
+}
+
+impl TemperatureUnit {
+  pub fn convert_from_celsius(&self, celsius: f64) -> f64 {
+    match self {
+      TemperatureUnit::Celsius => celsius,
+      TemperatureUnit::Fahrenheit => celsius * 1.8 + 32f64,
+      TemperatureUnit::Kelvin => celsius + 273.15,
+    }
+  }
+
+  /// Converts a difference between temperatures, which doesn't shift by the unit's zero point
+  pub fn convert_difference_from_celsius(&self, celsius: f64) -> f64 {
+    match self {
+      TemperatureUnit::Fahrenheit => celsius * 1.8,
+      _ => celsius,
+    }
+  }
+}
+
+impl Display for TemperatureUnit {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TemperatureUnit::Celsius => write!(f, "°C"),
+      TemperatureUnit::Fahrenheit => write!(f, "°F"),
+      TemperatureUnit::Kelvin => write!(f, "K"),
+    }
+  }
+}
+
+impl FromStr for TemperatureUnit {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "c" | "celsius" => Ok(TemperatureUnit::Celsius),
+      "f" | "fahrenheit" => Ok(TemperatureUnit::Fahrenheit),
+      "k" | "kelvin" => Ok(TemperatureUnit::Kelvin),
+      _ => Err(format!("Invalid temperature unit '{s}', expected c, f or k")),
+    }
+  }
+}
@@ -0,0 +1,430 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crossterm::{cursor, event, queue, terminal};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::Print;
+use sysinfo::{ComponentExt, DiskExt, NetworksExt, ProcessExt, ProcessorExt, SystemExt};
+
+use crate::bytes::{ByteFormat, ByteFormatConvert};
+use crate::color::Color;
+use crate::config::Config;
+use crate::fixed_system::FixedSystem;
+use crate::graph::{self, Scale, Thresholds};
+use crate::history::RollingHistory;
+use crate::metrics;
+use crate::util::TrimTrailingZerosToString;
+
+/// Colors of the dashboard, set by `top_accent`, `top_dim` and `top_text` in `[settings]`
+#[derive(Debug, Copy, Clone)]
+pub struct Theme {
+  pub accent: Color,
+  pub dim: Color,
+  pub text: Color,
+}
+
+impl Theme {
+  pub fn from_config(config: &Config) -> Self {
+    Self {
+      accent: config.setting("top_accent").unwrap_or(Color::Cyan),
+      dim: config.setting("top_dim").unwrap_or(Color::BrightBlack),
+      text: config.setting("top_text").unwrap_or(Color::White),
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Panel {
+  Cpu,
+  Memory,
+  Temperatures,
+  Network,
+  Disks,
+  Processes,
+}
+
+const PANELS: [Panel; 6] = [
+  Panel::Cpu,
+  Panel::Memory,
+  Panel::Temperatures,
+  Panel::Network,
+  Panel::Disks,
+  Panel::Processes,
+];
+
+impl Panel {
+  fn title(&self) -> &'static str {
+    match self {
+      Panel::Cpu => "CPU",
+      Panel::Memory => "Memory",
+      Panel::Temperatures => "Temperatures",
+      Panel::Network => "Network",
+      Panel::Disks => "Disks",
+      Panel::Processes => "Processes",
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ProcessSort {
+  Cpu,
+  Memory,
+  Pid,
+  Name,
+}
+
+impl ProcessSort {
+  fn next(&self) -> Self {
+    match self {
+      ProcessSort::Cpu => ProcessSort::Memory,
+      ProcessSort::Memory => ProcessSort::Pid,
+      ProcessSort::Pid => ProcessSort::Name,
+      ProcessSort::Name => ProcessSort::Cpu,
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Rect {
+  x: u16,
+  y: u16,
+  width: u16,
+  height: u16,
+}
+
+/// A line made of colored text
+type Line = Vec<(Color, String)>;
+
+/// Largest binary unit that keeps the value at least 1
+fn human_bytes(bytes: u64) -> String {
+  bytes.convert_to_display(ByteFormat::Bytes, ByteFormat::Auto).to_string()
+}
+
+struct Dashboard {
+  sys: FixedSystem,
+  recent: RollingHistory,
+  theme: Theme,
+  interval: Duration,
+  focus: usize,
+  scroll: [usize; PANELS.len()],
+  sort: ProcessSort,
+}
+
+impl Dashboard {
+  fn new(sys: FixedSystem, theme: Theme, interval: Duration) -> Self {
+    Self {
+      sys,
+      // enough for a graph across a wide terminal
+      recent: RollingHistory::new(interval.saturating_mul(512)),
+      theme,
+      interval,
+      focus: 0,
+      scroll: [0; PANELS.len()],
+      sort: ProcessSort::Cpu,
+    }
+  }
+
+  /// Records what the graphs show under the same keys and with the same sampling as the placeholders
+  fn refresh(&mut self) {
+    self.sys.refresh();
+
+    let now = Instant::now();
+    let cores = metrics::selectors(&self.sys, "cpu_usage");
+    let ifaces = metrics::selectors(&self.sys, "net_rx");
+    let samples = vec![("cpu_usage", None), ("mem_used", None)]
+      .into_iter()
+      .chain(cores.iter().map(|core| ("cpu_usage", Some(core.as_str()))))
+      .chain(ifaces.iter().flat_map(|iface| vec![("net_rx", Some(iface.as_str())), ("net_tx", Some(iface.as_str()))]));
+
+    for (name, selector) in samples {
+      if let Some(value) = metrics::sample(&self.sys, name, selector) {
+        self.recent.push(&metrics::key(name, selector), now, value);
+      }
+    }
+  }
+
+  /// Returns `false` when the dashboard should close
+  fn handle(&mut self, key: KeyEvent) -> bool {
+    let panel = self.focus;
+
+    match key.code {
+      KeyCode::Char('q') | KeyCode::Esc => return false,
+      KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+      KeyCode::Tab | KeyCode::Right => self.focus = (self.focus + 1) % PANELS.len(),
+      KeyCode::BackTab | KeyCode::Left => self.focus = (self.focus + PANELS.len() - 1) % PANELS.len(),
+      KeyCode::Char(n @ '1'..='6') => self.focus = n as usize - '1' as usize,
+      KeyCode::Down | KeyCode::Char('j') => self.scroll[panel] += 1,
+      KeyCode::Up | KeyCode::Char('k') => self.scroll[panel] = self.scroll[panel].saturating_sub(1),
+      KeyCode::PageDown => self.scroll[panel] += 10,
+      KeyCode::PageUp => self.scroll[panel] = self.scroll[panel].saturating_sub(10),
+      KeyCode::Home => self.scroll[panel] = 0,
+      KeyCode::Char('s') => self.sort = self.sort.next(),
+      _ => {}
+    }
+
+    true
+  }
+
+  fn layout(width: u16, height: u16) -> Vec<(Panel, Rect)> {
+    let top = (height * 3 / 10).max(7).min(height);
+    let middle = (height * 3 / 10).max(6).min(height - top);
+    let bottom = height - top - middle;
+    let cpu = width * 3 / 5;
+    let third = width / 3;
+
+    vec![
+      (Panel::Cpu, Rect { x: 0, y: 0, width: cpu, height: top }),
+      (Panel::Memory, Rect { x: cpu, y: 0, width: width - cpu, height: top }),
+      (Panel::Temperatures, Rect { x: 0, y: top, width: third, height: middle }),
+      (Panel::Network, Rect { x: third, y: top, width: third, height: middle }),
+      (Panel::Disks, Rect { x: third * 2, y: top, width: width - third * 2, height: middle }),
+      (Panel::Processes, Rect { x: 0, y: top + middle, width, height: bottom }),
+    ]
+  }
+
+  fn percent(&self, value: f64) -> String {
+    format!("{:>5}%", value.trim_trailing_zeros_with_precision(1))
+  }
+
+  fn lines(&self, panel: Panel, width: usize) -> Vec<Line> {
+    let theme = self.theme;
+
+    match panel {
+      Panel::Cpu => {
+        let global = self.sys.global_processor_info();
+        let processors = self.sys.processors();
+        let brand = processors.first().map(|it| it.brand()).unwrap_or_default();
+        let mut lines = vec![
+          vec![
+            (theme.text, format!("{brand} ")),
+            (theme.accent, self.percent(global.cpu_usage() as f64)),
+            (theme.dim, format!(" {} MHz", processors.first().map(|it| it.frequency()).unwrap_or_default())),
+          ],
+          vec![(theme.accent, graph::sparkline(&self.recent.last("cpu_usage", width), Scale::new(0f64, 100f64), width, Thresholds::default()))],
+        ];
+
+        for (index, processor) in processors.iter().enumerate() {
+          let usage = processor.cpu_usage() as f64;
+          let graph_width = width.saturating_sub(22);
+
+          lines.push(vec![
+            (theme.dim, format!("{index:>3} ")),
+            (theme.accent, graph::bar(usage, Scale::new(0f64, 100f64), 8, "█", "░", Thresholds::default())),
+            (theme.text, format!(" {} ", self.percent(usage))),
+            (theme.dim, graph::sparkline(&self.recent.last(&metrics::key("cpu_usage", Some(&index.to_string())), graph_width), Scale::new(0f64, 100f64), graph_width, Thresholds::default())),
+          ]);
+        }
+
+        lines
+      }
+      Panel::Memory => {
+        let mut lines = vec![];
+        let memory = [
+          ("RAM ", self.sys.used_memory(), self.sys.total_memory()),
+          ("Swap", self.sys.used_swap(), self.sys.total_swap()),
+        ];
+
+        for (label, used, total) in memory {
+          let usage = if total == 0 { 0f64 } else { used as f64 / total as f64 * 100f64 };
+
+          lines.push(vec![
+            (theme.text, format!("{label} ")),
+            (theme.accent, human_bytes(used * 1024)),
+            (theme.dim, format!(" / {} ", human_bytes(total * 1024))),
+            (theme.text, self.percent(usage)),
+          ]);
+          lines.push(vec![(theme.accent, graph::bar(usage, Scale::new(0f64, 100f64), width, "█", "░", Thresholds::default()))]);
+        }
+
+        let total = self.sys.total_memory() as f64;
+
+        lines.push(vec![(theme.dim, graph::sparkline(&self.recent.last("mem_used", width), Scale::new(0f64, total), width, Thresholds::default()))]);
+        lines
+      }
+      Panel::Temperatures => self.sys.components()
+        .iter()
+        .map(|component| {
+          let temp = component.temperature() as f64;
+          // max() is the highest reading so far rather than a limit, so only the critical temperature counts
+          let thresholds = Thresholds {
+            warn: None,
+            crit: component.critical().map(|it| it as f64).filter(|it| *it > 0f64),
+          };
+          let color = thresholds.color(temp).unwrap_or(theme.accent);
+
+          vec![
+            (theme.text, format!("{:<w$}", component.label(), w = width.saturating_sub(9))),
+            (color, format!("{:>7}°C", temp.trim_trailing_zeros_with_precision(1))),
+          ]
+        })
+        .collect(),
+      Panel::Network => {
+        let mut networks = self.sys.networks().iter().collect::<Vec<_>>();
+
+        networks.sort_by(|a, b| a.0.cmp(b.0));
+        networks
+          .into_iter()
+          .flat_map(|(name, _)| {
+            let rx = self.recent.last(&metrics::key("net_rx", Some(name)), width);
+            let tx = self.recent.last(&metrics::key("net_tx", Some(name)), 1);
+
+            vec![
+              vec![
+                (theme.text, format!("{name} ")),
+                (theme.accent, format!("↓ {}/s ", human_bytes(rx.last().copied().unwrap_or_default() as u64))),
+                (theme.dim, format!("↑ {}/s", human_bytes(tx.last().copied().unwrap_or_default() as u64))),
+              ],
+              vec![(theme.dim, graph::sparkline(&rx, Scale::fit(&rx), width, Thresholds::default()))],
+            ]
+          })
+          .collect()
+      }
+      Panel::Disks => self.sys.disks()
+        .iter()
+        .flat_map(|disk| {
+          let total = disk.total_space();
+          let used = metrics::used_space(disk);
+          let usage = if total == 0 { 0f64 } else { used as f64 / total as f64 * 100f64 };
+
+          vec![
+            vec![
+              (theme.text, format!("{} ", disk.mount_point().display())),
+              (theme.dim, format!("{} ", String::from_utf8_lossy(disk.file_system()))),
+              (theme.accent, human_bytes(used)),
+              (theme.dim, format!(" / {}", human_bytes(total))),
+            ],
+            vec![(theme.accent, graph::bar(usage, Scale::new(0f64, 100f64), width, "█", "░", Thresholds::default()))],
+          ]
+        })
+        .collect(),
+      Panel::Processes => {
+        let mut processes = self.sys.processes().values().collect::<Vec<_>>();
+
+        match self.sort {
+          ProcessSort::Cpu => processes.sort_by(|a, b| b.cpu_usage().total_cmp(&a.cpu_usage())),
+          ProcessSort::Memory => processes.sort_by_key(|it| std::cmp::Reverse(it.memory())),
+          ProcessSort::Pid => processes.sort_by_key(|it| it.pid()),
+          ProcessSort::Name => processes.sort_by(|a, b| a.name().cmp(b.name())),
+        }
+
+        let mut lines = vec![vec![(theme.dim, format!("{:>8}  {:>6}  {:>10}  NAME (sort: {:?}, s to change)", "PID", "CPU%", "MEM", self.sort))]];
+
+        lines.extend(processes.into_iter().map(|process| vec![
+          (theme.dim, format!("{:>8}  ", process.pid())),
+          (theme.accent, format!("{:>6}  ", process.cpu_usage().trim_trailing_zeros_with_precision(1))),
+          (theme.text, format!("{:>10}  ", human_bytes(process.memory() * 1024))),
+          (theme.text, process.name().to_string()),
+        ]));
+
+        lines
+      }
+    }
+  }
+
+  fn draw_panel(&self, out: &mut impl Write, panel: Panel, rect: Rect, focused: bool) -> crossterm::Result<()> {
+    if rect.width < 4 || rect.height < 3 {
+      return Ok(());
+    }
+
+    let inner = rect.width as usize - 2;
+    let border = if focused { self.theme.accent } else { self.theme.dim };
+    let index = PANELS.iter().position(|it| *it == panel).unwrap_or_default();
+    let title = format!(" {} {} ", index + 1, panel.title());
+    let title = title.chars().take(inner).collect::<String>();
+    let top = format!("┌{title}{}┐", "─".repeat(inner - title.chars().count()));
+
+    queue!(out, cursor::MoveTo(rect.x, rect.y), Print(border.fg()), Print(top))?;
+
+    let lines = self.lines(panel, inner);
+    let rows = rect.height as usize - 2;
+    // the process header stays in place when scrolling
+    let (header, lines) = match panel {
+      Panel::Processes if !lines.is_empty() => lines.split_at(1),
+      _ => lines.split_at(0),
+    };
+    let scroll = self.scroll[index].min(lines.len().saturating_sub(rows - header.len()));
+    let visible = header.iter().chain(lines.iter().skip(scroll));
+
+    for row in 0..rows {
+      queue!(out, cursor::MoveTo(rect.x, rect.y + 1 + row as u16), Print(border.fg()), Print("│"))?;
+
+      let mut remaining = inner;
+
+      if let Some(line) = visible.clone().nth(row) {
+        for (color, text) in line {
+          let text = text.chars().take(remaining).collect::<String>();
+
+          remaining -= text.chars().count();
+          queue!(out, Print(color.fg()), Print(text))?;
+        }
+      }
+
+      queue!(out, Print(" ".repeat(remaining)), Print(border.fg()), Print("│"))?;
+    }
+
+    let bottom = format!("└{}┘", "─".repeat(inner));
+
+    queue!(out, cursor::MoveTo(rect.x, rect.y + rect.height - 1), Print(bottom), Print(Color::Reset.fg()))
+  }
+
+  fn draw(&self, out: &mut impl Write) -> crossterm::Result<()> {
+    let (width, height) = terminal::size()?;
+
+    for (panel, rect) in Self::layout(width, height) {
+      let focused = PANELS[self.focus] == panel;
+
+      self.draw_panel(out, panel, rect, focused)?;
+    }
+
+    out.flush()
+  }
+}
+
+/// Switches to the alternate screen in raw mode and back when dropped, even on errors
+struct TerminalGuard;
+
+impl TerminalGuard {
+  fn new(out: &mut impl Write) -> crossterm::Result<Self> {
+    terminal::enable_raw_mode()?;
+    queue!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    out.flush()?;
+
+    Ok(Self)
+  }
+}
+
+impl Drop for TerminalGuard {
+  fn drop(&mut self) {
+    let mut out = std::io::stdout();
+
+    let _ = queue!(out, cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = out.flush();
+    let _ = terminal::disable_raw_mode();
+  }
+}
+
+pub fn run(sys: FixedSystem, theme: Theme, interval: Duration) -> crossterm::Result<()> {
+  let mut out = std::io::stdout();
+  let _guard = TerminalGuard::new(&mut out)?;
+  let mut dashboard = Dashboard::new(sys, theme, interval);
+  let mut next_refresh = Instant::now();
+
+  queue!(out, terminal::Clear(terminal::ClearType::All))?;
+
+  loop {
+    if Instant::now() >= next_refresh {
+      dashboard.refresh();
+      next_refresh = Instant::now() + dashboard.interval;
+    }
+
+    dashboard.draw(&mut out)?;
+
+    if event::poll(next_refresh.saturating_duration_since(Instant::now()))? {
+      match event::read()? {
+        Event::Key(key) if !dashboard.handle(key) => return Ok(()),
+        Event::Resize(_, _) => queue!(out, terminal::Clear(terminal::ClearType::All))?,
+        _ => {}
+      }
+    }
+  }
+}
@@ -1,67 +1,327 @@
-fn clamp_max(usize: usize, max: usize) -> usize {
-  if usize >= max {
-    max
-  } else {
-    usize
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Rounding {
+  /// Half away from zero
+  Nearest,
+  /// Half to even, so rounding many values doesn't drift upwards
+  HalfEven,
+  Floor,
+  Ceil,
+  /// Towards zero, cutting off the decimals
+  Trunc,
+}
+
+impl Rounding {
+  fn apply(&self, value: f64) -> f64 {
+    match self {
+      Rounding::Nearest => value.round(),
+      Rounding::HalfEven => value.round_ties_even(),
+      Rounding::Floor => value.floor(),
+      Rounding::Ceil => value.ceil(),
+      Rounding::Trunc => value.trunc(),
+    }
   }
 }
 
-pub trait TrimTrailingZeros {
-  fn trim_trailing_zeros(&self) -> &str;
-  fn trim_trailing_zeros_with_precision(&self, precision: usize) -> &str;
+impl FromStr for Rounding {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "nearest" | "half_up" => Ok(Rounding::Nearest),
+      "even" | "half_even" => Ok(Rounding::HalfEven),
+      "floor" | "down" => Ok(Rounding::Floor),
+      "ceil" | "up" => Ok(Rounding::Ceil),
+      "trunc" => Ok(Rounding::Trunc),
+      _ => Err(format!("Invalid rounding '{s}', expected nearest, even, floor, ceil or trunc")),
+    }
+  }
 }
 
-pub trait TrimTrailingZerosToString {
-  fn trim_trailing_zeros(&self) -> String;
-  fn trim_trailing_zeros_with_precision(&self, precision: usize) -> String;
+/// How numbers are printed by placeholders
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NumberFormat {
+  /// Decimals to round to
+  pub precision: usize,
+  pub rounding: Rounding,
+  /// Keeps trailing zeros, so there are always `precision` decimals
+  pub keep_zeros: bool,
+  /// Minimum width, padded on the left
+  pub width: usize,
+  /// Pads with zeros after the sign instead of spaces
+  pub zero_pad: bool,
+  /// Prints `+` in front of positive numbers
+  pub sign: bool,
+  pub decimal_separator: char,
+  pub thousands_separator: char,
+  /// Groups the digits in front of the decimal separator by thousands
+  pub grouping: bool,
 }
 
-impl TrimTrailingZeros for str {
-  fn trim_trailing_zeros(&self) -> &str {
-    let mut length = self.len();
+impl Default for NumberFormat {
+  fn default() -> Self {
+    Self::with_precision(2)
+  }
+}
 
-    if !self.contains('.') {
-      return self;
+impl NumberFormat {
+  pub fn with_precision(precision: usize) -> Self {
+    Self {
+      precision,
+      rounding: Rounding::Nearest,
+      keep_zeros: false,
+      width: 0,
+      zero_pad: false,
+      sign: false,
+      decimal_separator: '.',
+      thousands_separator: ',',
+      grouping: false,
     }
+  }
 
-    for (index, char) in self.char_indices().rev() {
-      if char != '0' {
-        length = index + 1;
-        break
-      }
+  pub fn format(&self, value: f64) -> String {
+    if !value.is_finite() {
+      return format!("{value:>width$}", width = self.width);
     }
 
-    &self[..length].trim_end_matches('.')
+    let factor = 10f64.powi(self.precision.min(i32::MAX as usize) as i32);
+    let rounded = self.rounding.apply(value * factor) / factor;
+    // too many decimals to scale means there is nothing to round either
+    let rounded = if rounded.is_finite() { rounded } else { value };
+    // no `-0`
+    let rounded = if rounded == 0f64 { 0f64 } else { rounded };
+    let mut digits = format!("{:.*}", self.precision, rounded.abs());
+
+    if !self.keep_zeros && digits.contains('.') {
+      digits = digits.trim_end_matches('0').trim_end_matches('.').to_string();
+    }
+
+    let digits = self.localize(&digits);
+    let sign = match rounded {
+      rounded if rounded < 0f64 => "-",
+      _ if self.sign => "+",
+      _ => "",
+    };
+    let padding = self.width.saturating_sub(sign.len() + digits.chars().count());
+
+    if self.zero_pad {
+      format!("{sign}{}{digits}", "0".repeat(padding))
+    } else {
+      format!("{}{sign}{digits}", " ".repeat(padding))
+    }
   }
 
-  fn trim_trailing_zeros_with_precision(&self, precision: usize) -> &str {
-    match self.find('.') {
-      None => self,
-      Some(idx) => {
-        let length = clamp_max(idx + precision + 1, self.len());
+  /// Swaps in the decimal separator and groups the integer digits if enabled
+  fn localize(&self, digits: &str) -> String {
+    let (integer, decimals) = match digits.split_once('.') {
+      Some((integer, decimals)) => (integer, Some(decimals)),
+      None => (digits, None),
+    };
+    let mut out = String::with_capacity(digits.len() * 2);
 
-        (&self[..length]).trim_trailing_zeros()
+    for (index, char) in integer.chars().enumerate() {
+      if self.grouping && index > 0 && (integer.len() - index) % 3 == 0 {
+        out.push(self.thousands_separator);
       }
+
+      out.push(char);
+    }
+
+    if let Some(decimals) = decimals {
+      out.push(self.decimal_separator);
+      out.push_str(decimals);
     }
+
+    out
   }
 }
 
+pub trait TrimTrailingZerosToString {
+  fn trim_trailing_zeros_with_precision(&self, precision: usize) -> String;
+}
+
 impl TrimTrailingZerosToString for f32 {
-  fn trim_trailing_zeros(&self) -> String {
-    self.to_string().trim_trailing_zeros().to_string()
+  fn trim_trailing_zeros_with_precision(&self, precision: usize) -> String {
+    NumberFormat::with_precision(precision).format(*self as f64)
   }
+}
 
+impl TrimTrailingZerosToString for f64 {
   fn trim_trailing_zeros_with_precision(&self, precision: usize) -> String {
-    self.to_string().trim_trailing_zeros_with_precision(precision).to_string()
+    NumberFormat::with_precision(precision).format(*self)
   }
 }
 
-impl TrimTrailingZerosToString for f64 {
-  fn trim_trailing_zeros(&self) -> String {
-    self.to_string().trim_trailing_zeros().to_string()
+/// Parses durations like `90`, `60s`, `15m`, `1h`, `7d` or `1w`, a plain number is in seconds
+pub fn parse_duration(str: &str) -> Result<Duration, String> {
+  let str = str.trim();
+  let split = str
+    .find(|c: char| !c.is_ascii_digit() && c != '.')
+    .unwrap_or(str.len());
+  let (value, unit) = str.split_at(split);
+
+  let value = f64::from_str(value)
+    .map_err(|_| format!("Invalid duration '{str}'"))?;
+
+  let secs = match unit.trim() {
+    "ms" => value / 1000f64,
+    "" | "s" | "sec" => value,
+    "m" | "min" => value * 60f64,
+    "h" => value * 60f64 * 60f64,
+    "d" => value * 60f64 * 60f64 * 24f64,
+    "w" => value * 60f64 * 60f64 * 24f64 * 7f64,
+    unit => return Err(format!("Invalid duration unit '{unit}' in '{str}'")),
+  };
+
+  if !secs.is_finite() || secs < 0f64 {
+    return Err(format!("Invalid duration '{str}'"));
   }
 
-  fn trim_trailing_zeros_with_precision(&self, precision: usize) -> String {
-    self.to_string().trim_trailing_zeros_with_precision(precision).to_string()
+  Duration::try_from_secs_f64(secs).map_err(|_| format!("Duration '{str}' is too long"))
+}
+
+/// Durations like `3d 4h 12m` without the units that are zero, seconds only show under a minute
+pub fn format_duration(duration: Duration) -> String {
+  let secs = duration.as_secs();
+  let units = [(secs / 86400, "d"), (secs / 3600 % 24, "h"), (secs / 60 % 60, "m")];
+
+  if secs < 60 {
+    return format!("{secs}s");
+  }
+
+  units
+    .iter()
+    .filter(|(value, _)| *value > 0)
+    .map(|(value, unit)| format!("{value}{unit}"))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn format(secs: u64) -> String {
+    format_duration(Duration::from_secs(secs))
+  }
+
+  fn number(precision: usize) -> NumberFormat {
+    NumberFormat::with_precision(precision)
+  }
+
+  #[test]
+  fn rounds_up_to_the_next_integer() {
+    assert_eq!(number(2).format(99.999), "100");
+    assert_eq!(number(2).format(0.995), "1");
+    assert_eq!(number(0).format(9.5), "10");
+  }
+
+  #[test]
+  fn no_scientific_notation() {
+    assert_eq!(number(2).format(1e20), "100000000000000000000");
+    assert_eq!(number(2).format(1.5e-10), "0");
+    assert_eq!(number(12).format(1.5e-10), "0.00000000015");
+    assert_eq!(number(400).format(1.5), "1.5");
+  }
+
+  #[test]
+  fn rounding_modes() {
+    let round = |rounding: &str, value: f64| NumberFormat {
+      rounding: Rounding::from_str(rounding).unwrap(),
+      ..number(0)
+    }.format(value);
+
+    assert_eq!([2.5, -2.5, 3.5].map(|it| round("nearest", it)), ["3", "-3", "4"]);
+    assert_eq!([2.5, -2.5, 3.5].map(|it| round("even", it)), ["2", "-2", "4"]);
+    assert_eq!([2.5, -2.5, 3.5].map(|it| round("floor", it)), ["2", "-3", "3"]);
+    assert_eq!([2.5, -2.5, 3.5].map(|it| round("ceil", it)), ["3", "-2", "4"]);
+    assert_eq!([2.5, -2.5, 3.5].map(|it| round("trunc", it)), ["2", "-2", "3"]);
+    assert!(Rounding::from_str("sideways").is_err());
+  }
+
+  #[test]
+  fn keeps_zeros() {
+    let keep = NumberFormat { keep_zeros: true, ..number(2) };
+
+    assert_eq!(keep.format(1.5), "1.50");
+    assert_eq!(keep.format(2f64), "2.00");
+    assert_eq!(number(2).format(1.50), "1.5");
+    assert_eq!(number(2).format(2f64), "2");
+  }
+
+  #[test]
+  fn width_and_padding() {
+    let width = NumberFormat { width: 6, ..number(1) };
+    let zero_pad = NumberFormat { zero_pad: true, ..width };
+
+    assert_eq!(width.format(1.5), "   1.5");
+    assert_eq!(width.format(-1.5), "  -1.5");
+    assert_eq!(zero_pad.format(1.5), "0001.5");
+    assert_eq!(zero_pad.format(-1.5), "-001.5");
+    assert_eq!(NumberFormat { sign: true, ..zero_pad }.format(1.5), "+001.5");
+    assert_eq!(width.format(1234567.5), "1234567.5");
+    assert_eq!(width.format(f64::NAN), "   NaN");
+  }
+
+  #[test]
+  fn negative_values_rounding_to_zero() {
+    assert_eq!(number(2).format(-0.001), "0");
+    assert_eq!(number(0).format(-0.4), "0");
+    assert_eq!(NumberFormat { keep_zeros: true, ..number(2) }.format(-0.001), "0.00");
+    assert_eq!(NumberFormat { sign: true, ..number(2) }.format(-0.001), "+0");
+  }
+
+  #[test]
+  fn separators() {
+    let german = NumberFormat {
+      decimal_separator: ',',
+      thousands_separator: '.',
+      grouping: true,
+      ..number(2)
+    };
+
+    assert_eq!(german.format(1234567.891), "1.234.567,89");
+    assert_eq!(german.format(-1234.5), "-1.234,5");
+    assert_eq!(german.format(999f64), "999");
+    assert_eq!(NumberFormat { grouping: false, ..german }.format(1234.5), "1234,5");
+    assert_eq!(NumberFormat { thousands_separator: ' ', ..german }.format(12345f64), "12 345");
+  }
+
+  #[test]
+  fn parses_durations() {
+    assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+    assert_eq!(parse_duration(" 1.5 min "), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+    assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(60 * 60)));
+    assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+    assert_eq!(parse_duration("1w"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+    assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+  }
+
+  #[test]
+  fn invalid_durations() {
+    for str in ["", "m", "-5s", "1.2.3s", "5y", "inf", "NaN", "99999999999999999999999d", "99999999999999999999999w", &"9".repeat(400)] {
+      assert!(parse_duration(str).is_err(), "{}", str);
+    }
+  }
+
+  #[test]
+  fn durations() {
+    assert_eq!(format(0), "0s");
+    assert_eq!(format(59), "59s");
+    assert_eq!(format(60), "1m");
+    assert_eq!(format(61), "1m");
+    assert_eq!(format(3600), "1h");
+    assert_eq!(format(3 * 86400 + 4 * 3600 + 12 * 60), "3d 4h 12m");
+  }
+
+  #[test]
+  fn durations_with_zero_hours() {
+    assert_eq!(format(86400), "1d");
+    assert_eq!(format(86400 + 5 * 60), "1d 5m");
+    assert_eq!(format(2 * 86400 + 59), "2d");
   }
 }